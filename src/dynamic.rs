@@ -0,0 +1,164 @@
+use std::collections::HashSet;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::evaluator::{self, ExtBatchEvaluation};
+use crate::group::{self, BatchEvaluation, Configurable, Fame, RunHistory, Sampling};
+use crate::hall_of_fame::ExtHallOfFame;
+use crate::individual;
+use crate::memo;
+use crate::noise;
+use crate::robust;
+
+/// Dynamic optimization with `ExtMemoizationDE`: every `interval` epochs the best `sentinels`
+/// individuals are evaluated again, bypassing the memo. If the first evaluation of one of them
+/// moved by more than `threshold`, the environment has changed: the memo (with its open log),
+/// the noise samples and the hall of fame are cleared and the population responds.
+/// Without a memo there is nothing to invalidate, and `ExtConfiguredDE` refuses it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dynamic {
+    pub interval: usize,
+    pub sentinels: usize,
+    /// larger than the noise of the objective
+    pub threshold: f64,
+    pub response: Response,
+}
+
+/// After a change the whole population is evaluated again, and before that:
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Response {
+    /// nothing else
+    Reevaluate,
+    /// the worst `fraction` of the population is replaced with random individuals
+    RandomImmigrants { fraction: f64 },
+    /// every individual but the best moves by up to ±`step` in every gene
+    Hypermutation { step: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeRecord {
+    pub epoch: usize,
+    /// largest change of the first evaluation among the sentinels
+    pub drift: f64,
+}
+
+pub trait ExtDynamic<I> {
+    /// Evaluates the sentinels again; the drift if it exceeds `Dynamic::threshold`.
+    fn detect_change(&mut self) -> Option<f64>;
+    /// Clears everything evaluated in the old environment and applies `Dynamic::response`.
+    fn respond_to_change(&mut self);
+}
+
+impl<I, G> ExtDynamic<I> for G
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::BaseDE<I>
+        + group::ExtMemoization<I>
+        + BatchEvaluation<I>
+        + Configurable<I>
+        + RunHistory<I>
+        + Sampling<I>
+        + Fame<I>
+        + ExtHallOfFame<I>,
+{
+    fn detect_change(&mut self) -> Option<f64> {
+        let dynamic = self.options_as_ref().dynamic.clone()?;
+        let individuals = self.get_individuals();
+        let mut order: Vec<usize> = (0..individuals.len()).collect();
+        order.sort_by(|a, b| {
+            individuals[*b]
+                .get_evaluations()
+                .partial_cmp(individuals[*a].get_evaluations())
+                .unwrap()
+        });
+        let mut keys = HashSet::new();
+        let sentinels: Vec<I> = order
+            .into_iter()
+            .map(|i| &individuals[i])
+            .filter(|individual| keys.insert(self.memo_key(individual.get_features())))
+            .take(dynamic.sentinels)
+            .cloned()
+            .collect();
+
+        let mut fresh = sentinels.clone();
+        robust::evaluate_robust(self, &mut fresh, &mut |group: &mut G, individuals| {
+            group.evaluate_individuals(individuals);
+        });
+        // 評価に失敗したsentinelは比べない
+        let drift = sentinels
+            .iter()
+            .zip(fresh.iter())
+            .filter_map(|(old, new)| {
+                let (old, new) = (
+                    old.get_evaluations().first()?,
+                    new.get_evaluations().first()?,
+                );
+                (*new > f64::MIN).then(|| (new - old).abs())
+            })
+            .fold(0.0, f64::max);
+        if drift <= dynamic.threshold {
+            return None;
+        }
+        let epoch = self.history_as_ref().epochs;
+        self.history_as_mut()
+            .changes
+            .push(ChangeRecord { epoch, drift });
+        Some(drift)
+    }
+
+    fn respond_to_change(&mut self) {
+        let Some(dynamic) = self.options_as_ref().dynamic.clone() else {
+            return;
+        };
+        self.clear_memo();
+        // 再びopenした時に古い環境の評価値が読み込まれないよう、logも空にする
+        let header = memo::format_memo_header(self.get_fingerprint(), *self.memo_keying_as_ref());
+        if let Some(log) = self.memo_log_as_mut() {
+            log.rewrite(&header);
+        }
+        self.samples_as_mut().clear();
+        self.hall_of_fame_as_mut().clear();
+
+        let mut individuals = self.get_individuals().clone();
+        individuals.sort_by(|a, b| {
+            b.get_evaluations()
+                .partial_cmp(a.get_evaluations())
+                .unwrap()
+        });
+        let gene_len = self.get_gene_len();
+        match dynamic.response {
+            Response::Reevaluate => {}
+            Response::RandomImmigrants { fraction } => {
+                let count = (individuals.len() as f64 * fraction).round() as usize;
+                let kept = individuals.len() - count.min(individuals.len());
+                for individual in individuals[kept..].iter_mut() {
+                    *individual = I::from_length(gene_len, self.borrowed_random_generator());
+                }
+            }
+            Response::Hypermutation { step } => {
+                for individual in individuals.iter_mut().skip(1) {
+                    let rng = self.borrowed_random_generator();
+                    let genes = individual
+                        .get_genes()
+                        .iter()
+                        .map(|gene| (gene + step * rng.gen_range(-1.0..=1.0)).clamp(0.0, 1.0))
+                        .collect();
+                    *individual = I::from_genes(genes);
+                }
+            }
+        }
+        for individual in individuals.iter_mut() {
+            individual.set_features(individual.identificate());
+        }
+
+        let noisy = self.options_as_ref().noise.is_some();
+        if noisy {
+            robust::evaluate_robust(self, &mut individuals, &mut noise::evaluate_with_samples);
+        } else {
+            robust::evaluate_robust(self, &mut individuals, &mut evaluator::evaluate_with_memo);
+        }
+        self.set_individuals(individuals);
+        self.update_hall_of_fame();
+    }
+}
@@ -0,0 +1,368 @@
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::group::{self, BatchEvaluation, Configurable, RunHistory};
+use crate::individual;
+use crate::memo::MemoKey;
+
+/// Evaluates many individuals at once, e.g. with a vectorized kernel or an external solver
+/// (like scipy's `vectorized=True`). The individuals have their features set; the
+/// evaluations are returned in the same order.
+pub trait BatchEvaluator<I>: Send {
+    fn evaluate_batch(&mut self, individuals: &[I]) -> Vec<Vec<f64>>;
+}
+
+impl<I, F> BatchEvaluator<I> for F
+where
+    F: FnMut(&[I]) -> Vec<Vec<f64>> + Send,
+{
+    fn evaluate_batch(&mut self, individuals: &[I]) -> Vec<Vec<f64>> {
+        self(individuals)
+    }
+}
+
+/// Isolation of evaluations that panic (`Options::guard`), or hang with
+/// `ExtEvaluationTimeout::set_evaluation_timeout`. A failed individual is tried again up to
+/// `retries` times, then handled by `policy`; failures are logged to `History::failures`
+/// and never memoized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Guard {
+    pub retries: usize,
+    pub policy: FailurePolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailurePolicy {
+    /// the failed individual gets `[f64::MIN]` and loses every selection
+    #[default]
+    Worst,
+    /// advance_epoch replaces a failed trial with a new trial, at most `max_resamples` times
+    /// per target; the failed individual has empty evaluations meanwhile
+    Resample { max_resamples: usize },
+    /// `Worst` until `max_failures` failures in total, then `History::aborted` is set and
+    /// advance_epoch stops
+    Abort { max_failures: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailureRecord {
+    pub epoch: usize,
+    /// features joined with "," (the `MemoKeying::Joined` key)
+    pub key: String,
+    pub attempts: usize,
+    /// the panic message, or the timeout
+    pub reason: String,
+}
+
+/// (index, attempts, reason) of one failed individual
+type Failure = (usize, usize, String);
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "panic".to_string(),
+        },
+    }
+}
+
+/// One guarded `individual::Minimum::evaluate`
+fn try_evaluate<I>(individual: &I) -> Result<Vec<f64>, String>
+where
+    I: individual::Minimum,
+{
+    panic::catch_unwind(AssertUnwindSafe(|| individual.evaluate())).map_err(panic_message)
+}
+
+/// `try_evaluate` on its own thread, which is left behind when it hangs
+fn try_evaluate_with_timeout<I>(individual: &I, timeout: Duration) -> Result<Vec<f64>, String>
+where
+    I: individual::Minimum + Clone + Send + 'static,
+{
+    let (sender, receiver) = channel();
+    let individual = individual.clone();
+    std::thread::spawn(move || {
+        let _ = sender.send(try_evaluate(&individual));
+    });
+    receiver
+        .recv_timeout(timeout)
+        .unwrap_or_else(|_| Err(format!("timed out after {:?}", timeout)))
+}
+
+type TimedEvaluation<I> = Box<dyn Fn(&I) -> Result<Vec<f64>, String> + Send>;
+
+/// The batch evaluator of a group. Without one, `individual::Minimum::evaluate` is used.
+pub struct Evaluator<I> {
+    batch: Option<Box<dyn BatchEvaluator<I>>>,
+    /// `try_evaluate` with the timeout of `ExtEvaluationTimeout`
+    timed: Option<TimedEvaluation<I>>,
+}
+
+impl<I> Default for Evaluator<I> {
+    fn default() -> Self {
+        Self {
+            batch: None,
+            timed: None,
+        }
+    }
+}
+
+impl<I> fmt::Debug for Evaluator<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.batch, &self.timed) {
+            (Some(_), _) => write!(f, "Evaluator(batch)"),
+            (None, Some(_)) => write!(f, "Evaluator(individual, timed)"),
+            (None, None) => write!(f, "Evaluator(individual)"),
+        }
+    }
+}
+
+impl<I> Evaluator<I> {
+    pub fn new(evaluator: Option<Box<dyn BatchEvaluator<I>>>) -> Self {
+        Self {
+            batch: evaluator,
+            timed: None,
+        }
+    }
+
+    pub fn is_batch(&self) -> bool {
+        self.batch.is_some()
+    }
+}
+
+impl<I> Evaluator<I>
+where
+    I: individual::Minimum,
+{
+    /// Sets the evaluations of `individuals`, whose features are set.
+    pub fn evaluate(&mut self, individuals: &mut [I]) {
+        if individuals.is_empty() {
+            return;
+        }
+        match self.batch.as_mut() {
+            Some(evaluator) => {
+                let evaluations = evaluator.evaluate_batch(individuals);
+                assert_eq!(evaluations.len(), individuals.len());
+                for (individual, evaluation) in individuals.iter_mut().zip(evaluations) {
+                    individual.set_evaluations(evaluation);
+                }
+            }
+            None => {
+                for individual in individuals.iter_mut() {
+                    individual.set_evaluations(individual.evaluate());
+                }
+            }
+        }
+    }
+
+    /// `evaluate` that does not panic. Failed individuals keep their evaluations.
+    /// A panicking batch is evaluated again one individual at a time, to find the culprits.
+    pub fn evaluate_guarded(&mut self, individuals: &mut [I], guard: &Guard) -> Vec<Failure> {
+        if individuals.is_empty() {
+            return vec![];
+        }
+        let Some(evaluator) = self.batch.as_mut() else {
+            let mut failures = vec![];
+            for (i, individual) in individuals.iter_mut().enumerate() {
+                let mut reason = String::new();
+                let mut attempts = 0;
+                let result = (0..=guard.retries).find_map(|_| {
+                    attempts += 1;
+                    let result = match &self.timed {
+                        Some(timed) => timed(individual),
+                        None => try_evaluate(individual),
+                    };
+                    result.map_err(|e| reason = e).ok()
+                });
+                match result {
+                    Some(evaluation) => individual.set_evaluations(evaluation),
+                    None => failures.push((i, attempts, reason)),
+                }
+            }
+            return failures;
+        };
+
+        let batch = panic::catch_unwind(AssertUnwindSafe(|| evaluator.evaluate_batch(individuals)));
+        if let Ok(evaluations) = batch {
+            if evaluations.len() == individuals.len() {
+                for (individual, evaluation) in individuals.iter_mut().zip(evaluations) {
+                    individual.set_evaluations(evaluation);
+                }
+                return vec![];
+            }
+        }
+        let mut failures = vec![];
+        for (i, individual) in individuals.iter_mut().enumerate() {
+            let mut reason = String::new();
+            let mut attempts = 0;
+            let single = std::slice::from_ref(&*individual);
+            let result = (0..=guard.retries).find_map(|_| {
+                attempts += 1;
+                match panic::catch_unwind(AssertUnwindSafe(|| evaluator.evaluate_batch(single))) {
+                    Ok(mut evaluations) if evaluations.len() == 1 => evaluations.pop(),
+                    Ok(evaluations) => {
+                        reason = format!("{} evaluations for 1 individual", evaluations.len());
+                        None
+                    }
+                    Err(payload) => {
+                        reason = panic_message(payload);
+                        None
+                    }
+                }
+            });
+            match result {
+                Some(evaluation) => individual.set_evaluations(evaluation),
+                None => failures.push((i, attempts, reason)),
+            }
+        }
+        failures
+    }
+}
+
+pub trait ExtBatchEvaluation<I> {
+    /// `None` goes back to `individual::Minimum::evaluate`
+    fn set_batch_evaluator(&mut self, evaluator: Option<Box<dyn BatchEvaluator<I>>>);
+    /// Indexes of the individuals whose evaluation failed (`Options::guard`); always empty
+    /// without a guard, where a failure panics.
+    fn evaluate_individuals(&mut self, individuals: &mut [I]) -> Vec<usize>;
+    /// Sets `History::aborted` when `FailurePolicy::Abort` has seen enough failures.
+    fn check_failures(&mut self);
+}
+
+impl<I, G> ExtBatchEvaluation<I> for G
+where
+    I: individual::Minimum,
+    G: BatchEvaluation<I> + Configurable<I> + RunHistory<I>,
+{
+    fn set_batch_evaluator(&mut self, evaluator: Option<Box<dyn BatchEvaluator<I>>>) {
+        self.evaluator_as_mut().batch = evaluator;
+    }
+
+    fn evaluate_individuals(&mut self, individuals: &mut [I]) -> Vec<usize> {
+        let Some(guard) = self.options_as_ref().guard.clone() else {
+            self.evaluator_as_mut().evaluate(individuals);
+            return vec![];
+        };
+        let failures = self
+            .evaluator_as_mut()
+            .evaluate_guarded(individuals, &guard);
+        let mut failed = vec![];
+        for (i, attempts, reason) in failures {
+            let individual = &mut individuals[i];
+            let history = self.history_as_mut();
+            history.failures.push(FailureRecord {
+                epoch: history.epochs,
+                key: individual.get_features().iter().join(","),
+                attempts,
+                reason,
+            });
+            match guard.policy {
+                FailurePolicy::Resample { .. } => individual.set_evaluations(vec![]),
+                FailurePolicy::Worst | FailurePolicy::Abort { .. } => {
+                    individual.set_evaluations(vec![f64::MIN])
+                }
+            }
+            failed.push(i);
+        }
+        failed
+    }
+
+    fn check_failures(&mut self) {
+        let Some(FailurePolicy::Abort { max_failures }) = self
+            .options_as_ref()
+            .guard
+            .as_ref()
+            .map(|guard| guard.policy)
+        else {
+            return;
+        };
+        if self.history_as_ref().failures.len() >= max_failures {
+            self.history_as_mut().aborted = true;
+        }
+    }
+}
+
+pub trait ExtEvaluationTimeout<I> {
+    /// Limit of one `individual::Minimum::evaluate` under `Options::guard`; a late evaluation
+    /// fails. The evaluation runs on its own thread, which is left behind when it hangs.
+    /// Batch evaluators are not timed (`ProcessPool::timeout` is their limit).
+    fn set_evaluation_timeout(&mut self, timeout: Option<Duration>);
+}
+
+impl<I, G> ExtEvaluationTimeout<I> for G
+where
+    I: individual::Minimum + Clone + Send + 'static,
+    G: BatchEvaluation<I>,
+{
+    fn set_evaluation_timeout(&mut self, timeout: Option<Duration>) {
+        self.evaluator_as_mut().timed = timeout.map(|timeout| {
+            Box::new(move |individual: &I| try_evaluate_with_timeout(individual, timeout))
+                as TimedEvaluation<I>
+        });
+    }
+}
+
+/// memoを引いてから、残りを1回のバッチで評価する.
+/// 同じ世代に同じkeyが複数あれば評価は1回で、2回目以降はhitになる. 失敗した評価はmemoに入れない
+pub fn evaluate_with_memo<I, G>(group: &mut G, individuals: &mut [I])
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::ExtMemoization<I> + BatchEvaluation<I> + Configurable<I> + RunHistory<I>,
+{
+    let keys: Vec<MemoKey<I::Feature>> = individuals
+        .iter()
+        .map(|individual| group.memo_key(individual.get_features()))
+        .collect();
+    let mut pending: HashSet<&MemoKey<I::Feature>> = HashSet::new();
+    let mut misses: Vec<usize> = vec![];
+    let mut duplicates: Vec<usize> = vec![];
+    for (i, individual) in individuals.iter_mut().enumerate() {
+        if pending.contains(&keys[i]) {
+            duplicates.push(i);
+        } else if let Some(evaluation) = group.lookup_memo(&keys[i]) {
+            individual.set_evaluations(evaluation);
+        } else {
+            pending.insert(&keys[i]);
+            misses.push(i);
+        }
+    }
+
+    let mut batch: Vec<I> = misses.iter().map(|i| individuals[*i].clone()).collect();
+    let failed: HashSet<usize> = group.evaluate_individuals(&mut batch).into_iter().collect();
+    let mut failures: HashMap<&MemoKey<I::Feature>, Vec<f64>> = HashMap::new();
+    for (j, (i, evaluated)) in misses.into_iter().zip(batch).enumerate() {
+        if failed.contains(&j) {
+            failures.insert(&keys[i], evaluated.get_evaluations().clone());
+        } else {
+            group.insert_memo(keys[i].clone(), evaluated.get_evaluations().clone());
+        }
+        individuals[i] = evaluated;
+    }
+    for i in duplicates {
+        if let Some(evaluation) = failures.get(&keys[i]) {
+            individuals[i].set_evaluations(evaluation.clone());
+            continue;
+        }
+        let evaluation = group.lookup_memo(&keys[i]);
+        // bounded memoで追い出されていれば評価し直す
+        let evaluation = evaluation.unwrap_or_else(|| {
+            let mut single = vec![individuals[i].clone()];
+            let failed = group.evaluate_individuals(&mut single);
+            let evaluation = single[0].get_evaluations().clone();
+            if failed.is_empty() {
+                group.insert_memo(keys[i].clone(), evaluation.clone());
+            }
+            evaluation
+        });
+        individuals[i].set_evaluations(evaluation);
+    }
+    group.check_failures();
+}
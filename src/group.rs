@@ -1,4 +1,11 @@
+use crate::evaluator;
+use crate::hall_of_fame;
 use crate::individual;
+use crate::init;
+use crate::memo;
+use crate::method;
+use crate::noise;
+use crate::surrogate;
 use itertools::Itertools;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde::de::DeserializeOwned;
@@ -6,7 +13,6 @@ use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fs::{read_to_string, File};
 use std::io::{BufRead, BufReader, Write};
-use std::str::FromStr;
 
 pub trait Minimum<I>
 where
@@ -17,9 +23,51 @@ where
     fn get_individuals(&self) -> &Vec<I>;
     fn set_random_generator(&mut self, random_generator: StdRng);
     fn borrowed_random_generator(&mut self) -> &mut StdRng;
+    /// 1体だけ置き換える. 既定の実装は集団全体を複製するので、Groupでは上書きしている
+    fn set_individual(&mut self, index: usize, individual: I)
+    where
+        I: Clone,
+    {
+        let mut individuals = self.get_individuals().clone();
+        individuals[index] = individual;
+        self.set_individuals(individuals);
+    }
+}
+
+/// A memo file or a checkpoint written for another version of the problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FingerprintMismatch {
+    pub expected: String,
+    pub found: Option<String>,
+}
+
+impl std::fmt::Display for FingerprintMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "fingerprint mismatch: expected {:?}, found {:?}",
+            self.expected, self.found
+        )
+    }
 }
 
-type MEMO = HashMap<String, Vec<f64>>;
+impl std::error::Error for FingerprintMismatch {}
+
+/// Without an expected fingerprint anything is accepted.
+pub fn check_fingerprint(
+    expected: Option<&str>,
+    found: Option<&str>,
+) -> Result<(), FingerprintMismatch> {
+    match expected {
+        Some(expected) if found != Some(expected) => Err(FingerprintMismatch {
+            expected: expected.to_string(),
+            found: found.map(str::to_string),
+        }),
+        _ => Ok(()),
+    }
+}
+
+pub type Memo<F> = HashMap<memo::MemoKey<F>, Vec<f64>>;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Group<I>
@@ -27,10 +75,32 @@ where
     I: individual::Minimum,
 {
     individuals: Vec<I>,
+    #[serde(default)]
+    fingerprint: Option<String>,
     #[serde(skip)]
     random_generator: Option<StdRng>,
     #[serde(skip)]
-    memo: MEMO,
+    memo: Memo<I::Feature>,
+    #[serde(skip)]
+    memo_log: Option<memo::MemoLog>,
+    #[serde(skip)]
+    memo_keying: memo::MemoKeying,
+    #[serde(skip)]
+    memo_hasher: Option<memo::FeatureHash<I::Feature>>,
+    #[serde(skip)]
+    memo_cache: memo::MemoCache<I::Feature>,
+    #[serde(skip)]
+    options: method::Options,
+    #[serde(default)]
+    history: method::History,
+    #[serde(default = "hall_of_fame::HallOfFame::default")]
+    hall_of_fame: hall_of_fame::HallOfFame<I>,
+    #[serde(skip)]
+    surrogate: surrogate::SurrogateState<I::Feature>,
+    #[serde(skip, default = "evaluator::Evaluator::default")]
+    evaluator: evaluator::Evaluator<I>,
+    #[serde(default, with = "noise::sample_keys")]
+    samples: noise::SampleMemo<I::Feature>,
 }
 
 impl<I> Minimum<I> for Group<I>
@@ -40,8 +110,19 @@ where
     fn new() -> Self {
         Self {
             individuals: vec![],
+            fingerprint: None,
             random_generator: Some(StdRng::seed_from_u64(0)),
             memo: HashMap::new(),
+            memo_log: None,
+            memo_keying: memo::MemoKeying::Joined,
+            memo_hasher: None,
+            memo_cache: memo::MemoCache::default(),
+            options: method::Options::default(),
+            history: method::History::default(),
+            hall_of_fame: hall_of_fame::HallOfFame::default(),
+            surrogate: surrogate::SurrogateState::default(),
+            evaluator: evaluator::Evaluator::default(),
+            samples: noise::SampleMemo::new(),
         }
     }
     fn set_individuals(&mut self, individuals: Vec<I>) {
@@ -50,6 +131,9 @@ where
     fn get_individuals(&self) -> &Vec<I> {
         &self.individuals
     }
+    fn set_individual(&mut self, index: usize, individual: I) {
+        self.individuals[index] = individual;
+    }
     fn set_random_generator(&mut self, random_generator: StdRng) {
         self.random_generator = Some(random_generator);
     }
@@ -58,6 +142,143 @@ where
     }
 }
 
+pub trait Versioned<I>
+where
+    I: individual::Minimum,
+{
+    /// evaluate/identificateのversion. memoやcheckpointに記録され、読み込み時に照合される
+    fn set_fingerprint(&mut self, fingerprint: Option<String>);
+    fn get_fingerprint(&self) -> Option<&str>;
+}
+
+impl<I> Versioned<I> for Group<I>
+where
+    I: individual::Minimum,
+{
+    fn set_fingerprint(&mut self, fingerprint: Option<String>) {
+        self.fingerprint = fingerprint;
+    }
+    fn get_fingerprint(&self) -> Option<&str> {
+        self.fingerprint.as_deref()
+    }
+}
+
+pub trait Configurable<I>
+where
+    I: individual::Minimum,
+{
+    fn options_as_ref(&self) -> &method::Options;
+    fn options_as_mut(&mut self) -> &mut method::Options;
+}
+
+impl<I> Configurable<I> for Group<I>
+where
+    I: individual::Minimum,
+{
+    fn options_as_ref(&self) -> &method::Options {
+        &self.options
+    }
+    fn options_as_mut(&mut self) -> &mut method::Options {
+        &mut self.options
+    }
+}
+
+pub trait RunHistory<I>
+where
+    I: individual::Minimum,
+{
+    fn history_as_ref(&self) -> &method::History;
+    fn history_as_mut(&mut self) -> &mut method::History;
+}
+
+impl<I> RunHistory<I> for Group<I>
+where
+    I: individual::Minimum,
+{
+    fn history_as_ref(&self) -> &method::History {
+        &self.history
+    }
+    fn history_as_mut(&mut self) -> &mut method::History {
+        &mut self.history
+    }
+}
+
+pub trait Fame<I>
+where
+    I: individual::Minimum,
+{
+    fn hall_of_fame_as_ref(&self) -> &hall_of_fame::HallOfFame<I>;
+    fn hall_of_fame_as_mut(&mut self) -> &mut hall_of_fame::HallOfFame<I>;
+}
+
+impl<I> Fame<I> for Group<I>
+where
+    I: individual::Minimum,
+{
+    fn hall_of_fame_as_ref(&self) -> &hall_of_fame::HallOfFame<I> {
+        &self.hall_of_fame
+    }
+    fn hall_of_fame_as_mut(&mut self) -> &mut hall_of_fame::HallOfFame<I> {
+        &mut self.hall_of_fame
+    }
+}
+
+pub trait SurrogateModeling<I>
+where
+    I: individual::Minimum,
+{
+    fn surrogate_as_ref(&self) -> &surrogate::SurrogateState<I::Feature>;
+    fn surrogate_as_mut(&mut self) -> &mut surrogate::SurrogateState<I::Feature>;
+}
+
+impl<I> SurrogateModeling<I> for Group<I>
+where
+    I: individual::Minimum,
+{
+    fn surrogate_as_ref(&self) -> &surrogate::SurrogateState<I::Feature> {
+        &self.surrogate
+    }
+    fn surrogate_as_mut(&mut self) -> &mut surrogate::SurrogateState<I::Feature> {
+        &mut self.surrogate
+    }
+}
+
+pub trait BatchEvaluation<I>
+where
+    I: individual::Minimum,
+{
+    fn evaluator_as_mut(&mut self) -> &mut evaluator::Evaluator<I>;
+}
+
+impl<I> BatchEvaluation<I> for Group<I>
+where
+    I: individual::Minimum,
+{
+    fn evaluator_as_mut(&mut self) -> &mut evaluator::Evaluator<I> {
+        &mut self.evaluator
+    }
+}
+
+pub trait Sampling<I>
+where
+    I: individual::Minimum,
+{
+    fn samples_as_ref(&self) -> &noise::SampleMemo<I::Feature>;
+    fn samples_as_mut(&mut self) -> &mut noise::SampleMemo<I::Feature>;
+}
+
+impl<I> Sampling<I> for Group<I>
+where
+    I: individual::Minimum,
+{
+    fn samples_as_ref(&self) -> &noise::SampleMemo<I::Feature> {
+        &self.samples
+    }
+    fn samples_as_mut(&mut self) -> &mut noise::SampleMemo<I::Feature> {
+        &mut self.samples
+    }
+}
+
 pub trait ExtMinimum<I>: Minimum<I>
 where
     I: individual::ExtMinimum,
@@ -65,6 +286,29 @@ where
     fn set_random_seed(&mut self, random_seed: u64);
     fn from_individuals(individuals: Vec<I>, random_generator: StdRng) -> Self;
     fn from_shape(individuals_len: usize, gene_len: usize, random_seed: u64) -> Self;
+    fn from_shape_with(
+        individuals_len: usize,
+        gene_len: usize,
+        random_seed: u64,
+        initialization: init::Initialization,
+    ) -> Self;
+    /// seedsを先頭に置き、残りはfrom_shapeと同様にランダムに生成する
+    fn from_seeds(
+        individuals_len: usize,
+        gene_len: usize,
+        seeds: Vec<Vec<f64>>,
+        random_seed: u64,
+    ) -> Self;
+    /// seedsのfeaturesをindividual::Minimum::encodeでgenesに戻し、from_seedsと同様に配置する.
+    /// encodeできないseedがあればpanicする
+    fn from_feature_seeds(
+        individuals_len: usize,
+        gene_len: usize,
+        seeds: &[Vec<I::Feature>],
+        random_seed: u64,
+    ) -> Self;
+    /// Options::noiseでは評価値はsampleの平均. 信頼区間は
+    /// noise::ExtNoise::get_best_with_confidenceで得られる
     fn get_best(&self) -> (usize, &I);
     fn get_gene_len(&self) -> usize;
 }
@@ -85,10 +329,41 @@ where
         g
     }
     fn from_shape(individuals_len: usize, gene_len: usize, random_seed: u64) -> Self {
+        G::from_shape_with(
+            individuals_len,
+            gene_len,
+            random_seed,
+            init::Initialization::Uniform,
+        )
+    }
+    fn from_shape_with(
+        individuals_len: usize,
+        gene_len: usize,
+        random_seed: u64,
+        initialization: init::Initialization,
+    ) -> Self {
+        let mut rg = StdRng::seed_from_u64(random_seed);
+
+        let points = init::sample(initialization, individuals_len, gene_len, &mut rg);
+        let individuals: Vec<I> = points.into_iter().map(I::from_genes).collect();
+        G::from_individuals(individuals, rg)
+    }
+    fn from_seeds(
+        individuals_len: usize,
+        gene_len: usize,
+        seeds: Vec<Vec<f64>>,
+        random_seed: u64,
+    ) -> Self {
+        assert!(seeds.len() <= individuals_len);
         let mut rg = StdRng::seed_from_u64(random_seed);
 
         let mut individuals = Vec::with_capacity(individuals_len);
-        for _ in 0..individuals_len {
+        for genes in seeds {
+            assert_eq!(genes.len(), gene_len);
+            assert!(genes.iter().all(|gene| (0.0..=1.0).contains(gene)));
+            individuals.push(I::from_genes(genes));
+        }
+        while individuals.len() < individuals_len {
             let mut genes = Vec::with_capacity(gene_len);
             for _ in 0..gene_len {
                 genes.push(rg.gen());
@@ -97,6 +372,23 @@ where
         }
         G::from_individuals(individuals, rg)
     }
+    fn from_feature_seeds(
+        individuals_len: usize,
+        gene_len: usize,
+        seeds: &[Vec<I::Feature>],
+        random_seed: u64,
+    ) -> Self {
+        let seeds = seeds
+            .iter()
+            .map(|features| {
+                let genes = I::encode(features).unwrap_or_else(|| {
+                    panic!("features {:?} cannot be encoded", features.iter().join(","))
+                });
+                genes.into_iter().map(|g| g.clamp(0.0, 1.0)).collect()
+            })
+            .collect();
+        G::from_seeds(individuals_len, gene_len, seeds, random_seed)
+    }
     fn get_best(&self) -> (usize, &I) {
         self.get_individuals()
             .iter()
@@ -116,12 +408,19 @@ where
 pub trait ExtSaveGroup<I> {
     fn save_to_json(&self, output_path: &str);
     fn load_from_json(input_path: &str, random_seed: u64) -> Self;
+    fn load_from_json_with_fingerprint(
+        input_path: &str,
+        random_seed: u64,
+        fingerprint: &str,
+    ) -> Result<Self, FingerprintMismatch>
+    where
+        Self: Sized;
 }
 
 impl<I, G> ExtSaveGroup<I> for G
 where
     I: individual::ExtMinimum + Serialize + DeserializeOwned,
-    G: Minimum<I> + Serialize + DeserializeOwned,
+    G: Minimum<I> + Versioned<I> + Serialize + DeserializeOwned,
 {
     fn save_to_json(&self, output_path: &str) {
         let mut file = File::create(output_path).unwrap();
@@ -134,73 +433,260 @@ where
         group.set_random_seed(random_seed);
         group
     }
+    fn load_from_json_with_fingerprint(
+        input_path: &str,
+        random_seed: u64,
+        fingerprint: &str,
+    ) -> Result<Self, FingerprintMismatch> {
+        let group = Self::load_from_json(input_path, random_seed);
+        check_fingerprint(Some(fingerprint), group.get_fingerprint())?;
+        Ok(group)
+    }
 }
 
 pub trait Memoization<I>
 where
     I: individual::ExtMinimum,
 {
-    fn memo_as_mut(&mut self) -> &mut MEMO;
-    fn memo_as_ref(&self) -> &MEMO;
+    /// MemoCacheを通らないので、追加や削除はExtMemoizationのメソッドで行う
+    fn memo_as_mut(&mut self) -> &mut Memo<I::Feature>;
+    fn memo_as_ref(&self) -> &Memo<I::Feature>;
+    fn memo_log_as_mut(&mut self) -> &mut Option<memo::MemoLog>;
+    fn memo_keying_as_ref(&self) -> &memo::MemoKeying;
+    fn memo_keying_as_mut(&mut self) -> &mut memo::MemoKeying;
+    fn memo_hasher_as_ref(&self) -> &Option<memo::FeatureHash<I::Feature>>;
+    fn memo_hasher_as_mut(&mut self) -> &mut Option<memo::FeatureHash<I::Feature>>;
+    fn memo_cache_as_ref(&self) -> &memo::MemoCache<I::Feature>;
+    fn memo_cache_as_mut(&mut self) -> &mut memo::MemoCache<I::Feature>;
 }
 
 impl<I> Memoization<I> for Group<I>
 where
     I: individual::ExtMinimum,
 {
-    fn memo_as_mut(&mut self) -> &mut MEMO {
+    fn memo_as_mut(&mut self) -> &mut Memo<I::Feature> {
         &mut self.memo
     }
-    fn memo_as_ref(&self) -> &MEMO {
+    fn memo_as_ref(&self) -> &Memo<I::Feature> {
         &self.memo
     }
+    fn memo_log_as_mut(&mut self) -> &mut Option<memo::MemoLog> {
+        &mut self.memo_log
+    }
+    fn memo_keying_as_ref(&self) -> &memo::MemoKeying {
+        &self.memo_keying
+    }
+    fn memo_keying_as_mut(&mut self) -> &mut memo::MemoKeying {
+        &mut self.memo_keying
+    }
+    fn memo_hasher_as_ref(&self) -> &Option<memo::FeatureHash<I::Feature>> {
+        &self.memo_hasher
+    }
+    fn memo_hasher_as_mut(&mut self) -> &mut Option<memo::FeatureHash<I::Feature>> {
+        &mut self.memo_hasher
+    }
+    fn memo_cache_as_ref(&self) -> &memo::MemoCache<I::Feature> {
+        &self.memo_cache
+    }
+    fn memo_cache_as_mut(&mut self) -> &mut memo::MemoCache<I::Feature> {
+        &mut self.memo_cache
+    }
 }
 
-pub trait ExtMemoization<I>: ExtMinimum<I> + Memoization<I>
+pub trait ExtMemoization<I>: ExtMinimum<I> + Memoization<I> + Versioned<I>
 where
     I: individual::ExtMinimum,
 {
     fn save_memo_to_csv(&self, output_path: &str);
+    /// fingerprintが一致しない場合はpanicする. 扱いたい場合はtry_load_memo_from_csvを使う
     fn load_memo_from_csv(&mut self, input_path: &str);
+    fn try_load_memo_from_csv(&mut self, input_path: &str) -> Result<(), FingerprintMismatch>;
     fn get_sorted_memo(&self) -> Vec<(String, Vec<f64>)>;
+    /// memoに追加し、memo logが開かれていればそちらにも追記する
+    fn insert_memo(&mut self, key: memo::MemoKey<I::Feature>, value: Vec<f64>);
+    /// memo logには書かずにmemoに追加する (読み込み用)
+    fn store_memo(&mut self, key: memo::MemoKey<I::Feature>, value: Vec<f64>);
+    /// hit/missを記録し、MemoBoundがあれば使用履歴も更新する
+    fn lookup_memo(&mut self, key: &memo::MemoKey<I::Feature>) -> Option<Vec<f64>>;
+    /// memo logには残る
+    fn remove_memo(&mut self, key: &memo::MemoKey<I::Feature>) -> Option<Vec<f64>>;
+    /// memo logには残る. MemoBoundと統計は変わらない
+    fn clear_memo(&mut self);
+    fn set_memo_bound(&mut self, bound: Option<memo::MemoBound>);
+    fn get_memo_stats(&self) -> memo::MemoStats;
+    fn memo_key(&self, features: &[I::Feature]) -> memo::MemoKey<I::Feature>;
+    /// 別のkeyingのkeyを現在のkeyingのkeyにする. hashされたkeyはfeaturesに戻せないのでNone
+    fn to_memo_key(&self, key: memo::MemoKey<I::Feature>) -> Option<memo::MemoKey<I::Feature>>;
+    /// `written`で書かれたmemo fileのkeyを現在のkeyingのkeyにする
+    fn read_memo_key(&self, key: String, written: memo::MemoKeying) -> memo::MemoKey<I::Feature>;
+    /// 既存のmemoとmemo logのkeyも変換する. Hash128から他への切り替えはmemoが空の時だけ
+    fn set_memo_keying(&mut self, keying: memo::MemoKeying);
 }
 
 impl<I, G> ExtMemoization<I> for G
 where
     I: individual::ExtMinimum,
-    G: Minimum<I> + Memoization<I>,
+    G: Minimum<I> + Memoization<I> + Versioned<I>,
 {
     fn save_memo_to_csv(&self, output_path: &str) {
         let mut file = File::create(output_path).unwrap();
-        let mut buf = String::new();
+        let mut buf = memo::format_memo_header(self.get_fingerprint(), *self.memo_keying_as_ref());
         for (key, value) in self.memo_as_ref().iter() {
-            let key = key;
-            let value = value.iter().join(",");
-            buf += &format!("{}:{}\n", key, value);
+            buf += &memo::format_memo_line(key, value);
         }
         file.write_all(buf.as_bytes()).unwrap();
     }
 
     fn load_memo_from_csv(&mut self, input_path: &str) {
+        self.try_load_memo_from_csv(input_path)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    fn try_load_memo_from_csv(&mut self, input_path: &str) -> Result<(), FingerprintMismatch> {
+        let found = memo::read_memo_fingerprint(input_path);
+        check_fingerprint(self.get_fingerprint(), found.as_deref())?;
+        let written = memo::read_memo_keying(input_path);
         for result in BufReader::new(File::open(input_path).unwrap()).lines() {
             let line = result.unwrap();
-            let key_value: Vec<&str> = line.split(":").collect();
-            assert_eq!(key_value.len(), 2);
-            let key = key_value[0].to_string();
-            let value = key_value[1]
-                .split(",")
-                .map(|x| f64::from_str(x).unwrap())
-                .collect();
-            self.memo_as_mut().insert(key, value);
+            if line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = memo::parse_memo_line(&line).unwrap();
+            let key = self.read_memo_key(key, written);
+            self.store_memo(key, value);
         }
+        Ok(())
     }
 
     fn get_sorted_memo(&self) -> Vec<(String, Vec<f64>)> {
-        let mut vec_memo = Vec::from_iter(self.memo_as_ref().clone());
+        let mut vec_memo = self
+            .memo_as_ref()
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect_vec();
         vec_memo.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
         vec_memo.reverse();
         vec_memo
     }
+
+    fn insert_memo(&mut self, key: memo::MemoKey<I::Feature>, value: Vec<f64>) {
+        if let Some(log) = self.memo_log_as_mut() {
+            log.append(&key, &value);
+        }
+        self.store_memo(key, value);
+    }
+
+    fn store_memo(&mut self, key: memo::MemoKey<I::Feature>, value: Vec<f64>) {
+        self.memo_cache_as_mut().track(&key, &value);
+        for evicted in self.memo_cache_as_mut().evict_for(&key) {
+            self.memo_as_mut().remove(&evicted);
+        }
+        self.memo_as_mut().insert(key, value);
+    }
+
+    fn lookup_memo(&mut self, key: &memo::MemoKey<I::Feature>) -> Option<Vec<f64>> {
+        match self.memo_as_ref().get(key).cloned() {
+            Some(value) => {
+                self.memo_cache_as_mut().hit(key);
+                Some(value)
+            }
+            None => {
+                self.memo_cache_as_mut().miss();
+                None
+            }
+        }
+    }
+
+    fn remove_memo(&mut self, key: &memo::MemoKey<I::Feature>) -> Option<Vec<f64>> {
+        self.memo_cache_as_mut().untrack(key);
+        self.memo_as_mut().remove(key)
+    }
+
+    fn clear_memo(&mut self) {
+        self.memo_cache_as_mut().clear();
+        self.memo_as_mut().clear();
+    }
+
+    fn set_memo_bound(&mut self, bound: Option<memo::MemoBound>) {
+        let mut cache = std::mem::take(self.memo_cache_as_mut());
+        cache.set_bound(bound, self.memo_as_ref());
+        for evicted in cache.enforce_bound() {
+            self.memo_as_mut().remove(&evicted);
+        }
+        *self.memo_cache_as_mut() = cache;
+    }
+
+    fn get_memo_stats(&self) -> memo::MemoStats {
+        self.memo_cache_as_ref().stats()
+    }
+
+    fn memo_key(&self, features: &[I::Feature]) -> memo::MemoKey<I::Feature> {
+        match self.memo_keying_as_ref() {
+            memo::MemoKeying::Joined => memo::MemoKey::Joined(features.iter().join(",")),
+            memo::MemoKeying::Hash128 => memo::MemoKey::Hash128(memo::hash_features(features)),
+            memo::MemoKeying::Features => {
+                let hash = self.memo_hasher_as_ref().unwrap()(features);
+                memo::MemoKey::Features(features.to_vec(), hash)
+            }
+        }
+    }
+
+    fn to_memo_key(&self, key: memo::MemoKey<I::Feature>) -> Option<memo::MemoKey<I::Feature>> {
+        let keying = *self.memo_keying_as_ref();
+        if key.keying() == keying {
+            return Some(key);
+        }
+        let features = match key {
+            memo::MemoKey::Hash128(_) => return None,
+            memo::MemoKey::Joined(joined) if keying == memo::MemoKeying::Hash128 => {
+                return Some(memo::MemoKey::Hash128(memo::hash_joined_key(&joined)));
+            }
+            memo::MemoKey::Joined(joined) => memo::parse_joined_key(&joined)
+                .unwrap_or_else(|| panic!("memo key {:?} cannot be parsed as features", joined)),
+            memo::MemoKey::Features(features, _) => features,
+        };
+        Some(self.memo_key(&features))
+    }
+
+    fn read_memo_key(&self, key: String, written: memo::MemoKeying) -> memo::MemoKey<I::Feature> {
+        let keying = *self.memo_keying_as_ref();
+        self.to_memo_key(memo::parse_written_key(key, written))
+            .unwrap_or_else(|| {
+                panic!(
+                    "hashed memo keys cannot be read with MemoKeying::{:?}",
+                    keying
+                )
+            })
+    }
+
+    fn set_memo_keying(&mut self, keying: memo::MemoKeying) {
+        assert!(
+            keying != memo::MemoKeying::Features || self.memo_hasher_as_ref().is_some(),
+            "MemoKeying::Features is set by ExtFeatureKeying::set_feature_memo_keying"
+        );
+        let current = *self.memo_keying_as_ref();
+        if current == memo::MemoKeying::Hash128 && keying != current {
+            // hashから元のfeaturesには戻せない
+            assert!(self.memo_as_ref().is_empty());
+        }
+        *self.memo_keying_as_mut() = keying;
+        if current != keying {
+            let memo = std::mem::take(self.memo_as_mut());
+            for (key, value) in memo {
+                let converted = self.to_memo_key(key.clone()).unwrap();
+                self.memo_cache_as_mut().rekey(&key, &converted, &value);
+                self.memo_as_mut().insert(converted, value);
+            }
+            // keyの大きさが変わるので、byte_budgetを超えることがある
+            for evicted in self.memo_cache_as_mut().enforce_bound() {
+                self.memo_as_mut().remove(&evicted);
+            }
+        }
+        let fingerprint = self.get_fingerprint().map(str::to_string);
+        if let Some(log) = self.memo_log_as_mut() {
+            log.convert_keys(fingerprint.as_deref(), keying);
+        }
+    }
 }
 
 pub trait BaseDE<I>: ExtMinimum<I>
@@ -266,12 +752,7 @@ where
                 gene += f_scale * (gene1 - gene2);
             }
             // [0.0, 1.0]がパラメータの範囲なため、超えていた場合は範囲内に収まるように修正する
-            if gene > 1.0 {
-                gene = 1.0
-            } else if gene < 0.0 {
-                gene = 0.0
-            }
-            genes.push(gene);
+            genes.push(gene.clamp(0.0, 1.0));
         }
         I::from_genes(genes)
     }
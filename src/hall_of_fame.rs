@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+use crate::group::{self, Fame};
+use crate::individual;
+
+/// The best distinct individuals ever seen, best first. Individuals with the same features
+/// count once.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HallOfFame<I> {
+    capacity: usize,
+    members: Vec<I>,
+}
+
+impl<I> Default for HallOfFame<I> {
+    /// capacity 0: nothing is kept
+    fn default() -> Self {
+        Self {
+            capacity: 0,
+            members: vec![],
+        }
+    }
+}
+
+impl<I> HallOfFame<I>
+where
+    I: individual::Minimum + Clone,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            members: vec![],
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Shrinking drops the worst members.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.members.truncate(capacity);
+    }
+
+    pub fn members(&self) -> &[I] {
+        &self.members
+    }
+
+    pub fn clear(&mut self) {
+        self.members.clear();
+    }
+
+    /// Returns whether `individual` entered the hall of fame.
+    pub fn update(&mut self, individual: &I) -> bool {
+        if individual.get_evaluations().is_empty() {
+            return false;
+        }
+        let better = |member: &I| {
+            member
+                .get_evaluations()
+                .partial_cmp(individual.get_evaluations())
+                .unwrap()
+                .is_lt()
+        };
+        if let Some(position) = self
+            .members
+            .iter()
+            .position(|member| member.get_features() == individual.get_features())
+        {
+            // 同じfeaturesなら評価の良い方を残す
+            if !better(&self.members[position]) {
+                return false;
+            }
+            self.members.remove(position);
+        }
+        let position = self
+            .members
+            .iter()
+            .position(better)
+            .unwrap_or(self.members.len());
+        if position >= self.capacity {
+            return false;
+        }
+        self.members.insert(position, individual.clone());
+        self.members.truncate(self.capacity);
+        true
+    }
+}
+
+pub trait ExtHallOfFame<I> {
+    /// keep the best `capacity` distinct individuals from now on
+    fn set_hall_of_fame_capacity(&mut self, capacity: usize);
+    /// best first
+    fn get_hall_of_fame(&self) -> Vec<&I>;
+    /// offer the current individuals to the hall of fame
+    fn update_hall_of_fame(&mut self);
+}
+
+impl<I, G> ExtHallOfFame<I> for G
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::Minimum<I> + Fame<I>,
+{
+    fn set_hall_of_fame_capacity(&mut self, capacity: usize) {
+        self.hall_of_fame_as_mut().set_capacity(capacity);
+    }
+
+    fn get_hall_of_fame(&self) -> Vec<&I> {
+        self.hall_of_fame_as_ref().members().iter().collect()
+    }
+
+    fn update_hall_of_fame(&mut self) {
+        if self.hall_of_fame_as_ref().capacity() == 0 {
+            return;
+        }
+        let mut hall_of_fame = std::mem::take(self.hall_of_fame_as_mut());
+        for individual in self.get_individuals() {
+            hall_of_fame.update(individual);
+        }
+        *self.hall_of_fame_as_mut() = hall_of_fame;
+    }
+}
@@ -21,11 +21,23 @@ pub trait Minimum {
 
     fn identificate(&self) -> Vec<Self::Feature>;
     fn evaluate(&self) -> Vec<f64>;
+
+    /// features --(encode)--> genes, the inverse of identificate.
+    /// Implement it to inject memo entries or hand-designed solutions into a group
+    /// (`group::ExtMinimum::from_feature_seeds`, `memo::ExtMemoSeed`).
+    /// The crate has no ask/tell interface, so nothing else calls it.
+    fn encode(_features: &[Self::Feature]) -> Option<Vec<f64>> {
+        None
+    }
 }
 
 pub trait ExtMinimum: Minimum {
     fn from_genes(gene: Vec<f64>) -> Self;
     fn from_length(length: usize, random_generator: &mut StdRng) -> Self;
+    /// `None` if encode is not implemented
+    fn from_features(features: &[Self::Feature]) -> Option<Self>
+    where
+        Self: Sized;
 
     fn is_better_than(&self, another: &Self) -> bool;
     fn cross(&self, another: &Self, own_ratio: f64, random_generator: &mut StdRng) -> Self;
@@ -47,6 +59,12 @@ where
         }
         Self::from_genes(gene)
     }
+    fn from_features(features: &[Self::Feature]) -> Option<Self> {
+        let genes = Self::encode(features)?;
+        let mut individual = Self::from_genes(genes.iter().map(|g| g.clamp(0.0, 1.0)).collect());
+        individual.set_features(individual.identificate());
+        Some(individual)
+    }
     fn is_better_than(&self, another: &Self) -> bool {
         let self_eval = self.get_evaluations();
         let another_eval = another.get_evaluations();
@@ -56,7 +74,7 @@ where
         }
     }
     fn cross(&self, another: &Self, another_ratio: f64, random_generator: &mut StdRng) -> Self {
-        debug_assert!(0.0 <= another_ratio && another_ratio < 1.0);
+        debug_assert!((0.0..1.0).contains(&another_ratio));
 
         let gene_len = self.get_genes().len();
         let must_choose_another = random_generator.gen_range(0..gene_len);
@@ -0,0 +1,293 @@
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+
+/// How `from_shape_with` places the first genes in [0.0, 1.0].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Initialization {
+    /// i.i.d. uniform, same as `from_shape`
+    #[default]
+    Uniform,
+    /// one gene per stratum in every dimension
+    LatinHypercube,
+    /// Sobol sequence scrambled by a random digital shift
+    Sobol,
+    /// Halton sequence with randomly permuted digits
+    Halton,
+}
+
+/// `individuals_len` gene vectors
+pub fn sample(
+    initialization: Initialization,
+    individuals_len: usize,
+    gene_len: usize,
+    random_generator: &mut StdRng,
+) -> Vec<Vec<f64>> {
+    match initialization {
+        Initialization::Uniform => uniform(individuals_len, gene_len, random_generator),
+        Initialization::LatinHypercube => {
+            latin_hypercube(individuals_len, gene_len, random_generator)
+        }
+        Initialization::Sobol => sobol(individuals_len, gene_len, random_generator),
+        Initialization::Halton => halton(individuals_len, gene_len, random_generator),
+    }
+}
+
+pub fn uniform(
+    individuals_len: usize,
+    gene_len: usize,
+    random_generator: &mut StdRng,
+) -> Vec<Vec<f64>> {
+    let mut points = Vec::with_capacity(individuals_len);
+    for _ in 0..individuals_len {
+        let mut genes = Vec::with_capacity(gene_len);
+        for _ in 0..gene_len {
+            genes.push(random_generator.gen());
+        }
+        points.push(genes);
+    }
+    points
+}
+
+pub fn latin_hypercube(
+    individuals_len: usize,
+    gene_len: usize,
+    random_generator: &mut StdRng,
+) -> Vec<Vec<f64>> {
+    let mut points = vec![Vec::with_capacity(gene_len); individuals_len];
+    for _ in 0..gene_len {
+        let mut strata: Vec<usize> = (0..individuals_len).collect();
+        strata.shuffle(random_generator);
+        for (point, stratum) in points.iter_mut().zip(strata) {
+            let offset: f64 = random_generator.gen();
+            point.push((stratum as f64 + offset) / individuals_len as f64);
+        }
+    }
+    points
+}
+
+const SOBOL_BITS: usize = 32;
+
+/// Initial direction numbers m_1..m_s of the dimensions 2..=21 (Joe and Kuo).
+/// Further dimensions get random odd m_i < 2^i, which still gives a valid Sobol sequence.
+const SOBOL_INITIAL_M: [&[u32]; 20] = [
+    &[1],
+    &[1, 3],
+    &[1, 3, 1],
+    &[1, 1, 1],
+    &[1, 1, 3, 3],
+    &[1, 3, 5, 13],
+    &[1, 1, 5, 5, 17],
+    &[1, 1, 5, 5, 5],
+    &[1, 1, 7, 11, 19],
+    &[1, 1, 5, 1, 1],
+    &[1, 1, 1, 3, 11],
+    &[1, 3, 5, 5, 31],
+    &[1, 3, 3, 9, 7, 49],
+    &[1, 1, 1, 15, 21, 21],
+    &[1, 3, 1, 13, 27, 49],
+    &[1, 1, 1, 15, 7, 5],
+    &[1, 3, 1, 15, 13, 25],
+    &[1, 1, 5, 5, 19, 61],
+    &[1, 3, 7, 11, 23, 15, 103],
+    &[1, 3, 7, 13, 13, 15, 69],
+];
+
+/// x^e mod poly over GF(2)
+fn gf2_pow_mod(e: u64, poly: u64, degree: u32) -> u64 {
+    let mul_mod = |a: u64, b: u64| {
+        let mut result = 0;
+        let mut a = a;
+        let mut b = b;
+        while b != 0 {
+            if b & 1 == 1 {
+                result ^= a;
+            }
+            b >>= 1;
+            a <<= 1;
+            if a >> degree & 1 == 1 {
+                a ^= poly;
+            }
+        }
+        result
+    };
+    let mut result = 1;
+    let mut base = if degree == 1 { 1 } else { 0b10 };
+    let mut e = e;
+    while e != 0 {
+        if e & 1 == 1 {
+            result = mul_mod(result, base);
+        }
+        base = mul_mod(base, base);
+        e >>= 1;
+    }
+    result
+}
+
+fn is_primitive(poly: u64, degree: u32) -> bool {
+    let order = (1u64 << degree) - 1;
+    if gf2_pow_mod(order, poly, degree) != 1 {
+        return false;
+    }
+    let mut factors = vec![];
+    let mut n = order;
+    let mut p = 2;
+    while p * p <= n {
+        if n % p == 0 {
+            factors.push(p);
+            while n % p == 0 {
+                n /= p;
+            }
+        }
+        p += 1;
+    }
+    if n > 1 {
+        factors.push(n);
+    }
+    factors
+        .iter()
+        .all(|q| gf2_pow_mod(order / q, poly, degree) != 1)
+}
+
+/// (degree, inner coefficients) of the first `count` primitive polynomials, by degree then coefficients
+fn primitive_polynomials(count: usize) -> Vec<(u32, u64)> {
+    let mut polynomials = Vec::with_capacity(count);
+    let mut degree = 1;
+    while polynomials.len() < count {
+        for a in 0..1u64 << (degree - 1) {
+            let poly = 1 << degree | a << 1 | 1;
+            if is_primitive(poly, degree) {
+                polynomials.push((degree, a));
+                if polynomials.len() == count {
+                    break;
+                }
+            }
+        }
+        degree += 1;
+    }
+    polynomials
+}
+
+/// `polynomial` is `None` for the first dimension
+fn sobol_directions(
+    dimension: usize,
+    polynomial: Option<(u32, u64)>,
+    random_generator: &mut StdRng,
+) -> Vec<u32> {
+    let mut directions = vec![0u32; SOBOL_BITS];
+    let (degree, a) = match polynomial {
+        Some(polynomial) => polynomial,
+        None => {
+            for (k, v) in directions.iter_mut().enumerate() {
+                *v = 1 << (SOBOL_BITS - 1 - k);
+            }
+            return directions;
+        }
+    };
+    let s = degree as usize;
+    let mut m: Vec<u32> = match SOBOL_INITIAL_M.get(dimension - 1) {
+        Some(initial) if initial.len() == s => initial.to_vec(),
+        _ => (1..=s)
+            .map(|i| random_generator.gen_range(0..1u32 << (i - 1)) * 2 + 1)
+            .collect(),
+    };
+    for k in s..SOBOL_BITS {
+        let mut next = m[k - s] ^ (m[k - s] << s);
+        for j in 1..s {
+            if a >> (s - 1 - j) & 1 == 1 {
+                next ^= m[k - j] << j;
+            }
+        }
+        m.push(next);
+    }
+    for (k, v) in directions.iter_mut().enumerate() {
+        *v = m[k] << (SOBOL_BITS - 1 - k);
+    }
+    directions
+}
+
+pub fn sobol(
+    individuals_len: usize,
+    gene_len: usize,
+    random_generator: &mut StdRng,
+) -> Vec<Vec<f64>> {
+    // 初期値が乱数に依存しないように、方向数は固定のseedで作る
+    let mut direction_generator = <StdRng as rand::SeedableRng>::seed_from_u64(0);
+    let polynomials = primitive_polynomials(gene_len.saturating_sub(1));
+    let directions: Vec<Vec<u32>> = (0..gene_len)
+        .map(|d| {
+            let polynomial = d.checked_sub(1).map(|i| polynomials[i]);
+            sobol_directions(d, polynomial, &mut direction_generator)
+        })
+        .collect();
+    let shifts: Vec<u32> = (0..gene_len).map(|_| random_generator.gen()).collect();
+
+    let mut points = Vec::with_capacity(individuals_len);
+    let mut x = vec![0u32; gene_len];
+    for n in 0..individuals_len {
+        if n != 0 {
+            // Gray code: 直前の点から1つの方向数だけ変わる
+            let c = (n - 1).trailing_ones() as usize;
+            for d in 0..gene_len {
+                x[d] ^= directions[d][c];
+            }
+        }
+        points.push(
+            x.iter()
+                .zip(shifts.iter())
+                .map(|(x, shift)| (x ^ shift) as f64 / (1u64 << SOBOL_BITS) as f64)
+                .collect(),
+        );
+    }
+    points
+}
+
+fn primes(count: usize) -> Vec<u64> {
+    let mut primes = Vec::with_capacity(count);
+    let mut n = 2;
+    while primes.len() < count {
+        if primes
+            .iter()
+            .take_while(|p| *p * *p <= n)
+            .all(|p| n % p != 0)
+        {
+            primes.push(n);
+        }
+        n += 1;
+    }
+    primes
+}
+
+pub fn halton(
+    individuals_len: usize,
+    gene_len: usize,
+    random_generator: &mut StdRng,
+) -> Vec<Vec<f64>> {
+    let bases = primes(gene_len);
+    // 0は固定したまま1..baseの桁を並べ替える
+    let permutations: Vec<Vec<u64>> = bases
+        .iter()
+        .map(|base| {
+            let mut digits: Vec<u64> = (1..*base).collect();
+            digits.shuffle(random_generator);
+            digits.insert(0, 0);
+            digits
+        })
+        .collect();
+
+    let mut points = Vec::with_capacity(individuals_len);
+    for n in 1..=individuals_len as u64 {
+        let mut genes = Vec::with_capacity(gene_len);
+        for (base, permutation) in bases.iter().zip(permutations.iter()) {
+            let mut index = n;
+            let mut scale = 1.0 / *base as f64;
+            let mut gene = 0.0;
+            while index != 0 {
+                gene += permutation[(index % base) as usize] as f64 * scale;
+                index /= base;
+                scale /= *base as f64;
+            }
+            genes.push(gene);
+        }
+        points.push(genes);
+    }
+    points
+}
@@ -0,0 +1,216 @@
+use std::fmt::Debug;
+
+use rand::{rngs::StdRng, seq::index::sample, Rng, SeedableRng};
+
+use crate::group::{self, ExtMinimum, Memoization, Minimum};
+use crate::individual;
+use crate::memo::{ExtMemoMerge, MergePolicy};
+use crate::method::ExtMemoizationDE;
+
+/// advance_epochの引数. 島ごとに変えられる
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strategy {
+    pub best_or_rand: String,
+    pub difference_vector_count: usize,
+    pub f_scale: f64,
+    pub crossover_rate: f64,
+}
+
+/// Which islands send migrants to which.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// island i - 1 -> island i
+    Ring,
+    /// every island -> every other island
+    FullyConnected,
+    /// one randomly chosen island -> island i
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Migration {
+    /// the best individuals of the source replace the worst of the destination
+    BestReplacesWorst,
+    /// random individuals of the source replace random individuals of the destination
+    RandomReplacesRandom,
+}
+
+/// Several groups evolving on their own threads and exchanging individuals every
+/// `migration_interval` epochs. Each island keeps its own random generator and migration
+/// uses the archipelago's one, so results do not depend on thread scheduling.
+#[derive(Debug)]
+pub struct Archipelago<I>
+where
+    I: individual::Minimum,
+{
+    pub islands: Vec<group::Group<I>>,
+    pub strategies: Vec<Strategy>,
+    pub topology: Topology,
+    pub migration: Migration,
+    pub migration_interval: usize,
+    /// migrants per connection
+    pub migrants: usize,
+    /// pool the memos of all islands at every migration
+    pub shared_memo: bool,
+    epochs_since_migration: usize,
+    random_generator: StdRng,
+}
+
+impl<I> Archipelago<I>
+where
+    I: individual::ExtMinimum + Clone + Debug + Send,
+    I::Feature: Send,
+{
+    /// ring topology, best replaces worst, one migrant every 10 epochs
+    pub fn new(islands: Vec<group::Group<I>>, strategies: Vec<Strategy>, random_seed: u64) -> Self {
+        assert_eq!(islands.len(), strategies.len());
+        Self {
+            islands,
+            strategies,
+            topology: Topology::Ring,
+            migration: Migration::BestReplacesWorst,
+            migration_interval: 10,
+            migrants: 1,
+            shared_memo: false,
+            epochs_since_migration: 0,
+            random_generator: StdRng::seed_from_u64(random_seed),
+        }
+    }
+
+    pub fn advance_epoch(&mut self, epoch: usize) {
+        assert!(self.migration_interval > 0);
+        let mut remaining = epoch;
+        while remaining > 0 {
+            let epoch = remaining.min(self.migration_interval - self.epochs_since_migration);
+            std::thread::scope(|scope| {
+                for (island, strategy) in self.islands.iter_mut().zip(self.strategies.iter()) {
+                    scope.spawn(move || {
+                        island.advance_epoch(
+                            epoch,
+                            &strategy.best_or_rand,
+                            strategy.difference_vector_count,
+                            strategy.f_scale,
+                            strategy.crossover_rate,
+                        )
+                    });
+                }
+            });
+            remaining -= epoch;
+            self.epochs_since_migration += epoch;
+            if self.epochs_since_migration == self.migration_interval {
+                self.migrate();
+                if self.shared_memo {
+                    self.share_memo();
+                }
+                self.epochs_since_migration = 0;
+            }
+        }
+    }
+
+    /// (island, individual, the individual)
+    pub fn get_best(&self) -> (usize, usize, &I) {
+        self.islands
+            .iter()
+            .enumerate()
+            .map(|(island, g)| {
+                let (index, individual) = g.get_best();
+                (island, index, individual)
+            })
+            .max_by(|a, b| {
+                a.2.get_evaluations()
+                    .partial_cmp(b.2.get_evaluations())
+                    .unwrap()
+            })
+            .unwrap()
+    }
+
+    /// (source, destination)
+    fn connections(&mut self) -> Vec<(usize, usize)> {
+        let len = self.islands.len();
+        if len < 2 {
+            return vec![];
+        }
+        match self.topology {
+            Topology::Ring => (0..len).map(|i| ((i + len - 1) % len, i)).collect(),
+            Topology::FullyConnected => (0..len)
+                .flat_map(|dst| {
+                    (0..len)
+                        .filter(move |src| *src != dst)
+                        .map(move |src| (src, dst))
+                })
+                .collect(),
+            Topology::Random => (0..len)
+                .map(|dst| {
+                    let src = self.random_generator.gen_range(0..len - 1);
+                    (if src >= dst { src + 1 } else { src }, dst)
+                })
+                .collect(),
+        }
+    }
+
+    fn migrate(&mut self) {
+        // 移住元は移住前の集団から選ぶ
+        let snapshots: Vec<Vec<I>> = self
+            .islands
+            .iter()
+            .map(|g| g.get_individuals().clone())
+            .collect();
+        let mut incoming: Vec<Vec<I>> = vec![vec![]; self.islands.len()];
+        for (src, dst) in self.connections() {
+            let population = &snapshots[src];
+            let count = self.migrants.min(population.len());
+            let migrants: Vec<usize> = match self.migration {
+                Migration::BestReplacesWorst => sorted_indexes(population)[..count].to_vec(),
+                Migration::RandomReplacesRandom => {
+                    sample(&mut self.random_generator, population.len(), count).into_vec()
+                }
+            };
+            incoming[dst].extend(migrants.into_iter().map(|i| population[i].clone()));
+        }
+
+        for (dst, migrants) in incoming.into_iter().enumerate() {
+            let mut individuals = self.islands[dst].get_individuals().clone();
+            let count = migrants.len().min(individuals.len());
+            let replaced: Vec<usize> = match self.migration {
+                Migration::BestReplacesWorst => sorted_indexes(&individuals)
+                    .into_iter()
+                    .rev()
+                    .take(count)
+                    .collect(),
+                Migration::RandomReplacesRandom => {
+                    sample(&mut self.random_generator, individuals.len(), count).into_vec()
+                }
+            };
+            for (index, migrant) in replaced.into_iter().zip(migrants) {
+                individuals[index] = migrant;
+            }
+            self.islands[dst].set_individuals(individuals);
+        }
+    }
+
+    fn share_memo(&mut self) {
+        let mut pooled = group::Memo::<I::Feature>::new();
+        for island in self.islands.iter() {
+            for (key, value) in island.memo_as_ref() {
+                pooled.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        for island in self.islands.iter_mut() {
+            island
+                .merge_memos(&[&pooled], MergePolicy::KeepFirst)
+                .unwrap();
+        }
+    }
+}
+
+/// best first
+fn sorted_indexes<I: individual::Minimum>(individuals: &[I]) -> Vec<usize> {
+    let mut indexes: Vec<usize> = (0..individuals.len()).collect();
+    indexes.sort_by(|a, b| {
+        individuals[*b]
+            .get_evaluations()
+            .partial_cmp(individuals[*a].get_evaluations())
+            .unwrap()
+    });
+    indexes
+}
@@ -1,3 +1,15 @@
+pub mod dynamic;
+pub mod evaluator;
 pub mod group;
+pub mod hall_of_fame;
 pub mod individual;
+pub mod init;
+pub mod island;
+pub mod memo;
 pub mod method;
+pub mod noise;
+pub mod polish;
+pub mod process;
+pub mod remote;
+pub mod robust;
+pub mod surrogate;
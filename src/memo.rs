@@ -0,0 +1,1018 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::{rename, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::str::FromStr;
+
+use itertools::Itertools;
+
+use crate::group;
+use crate::individual;
+
+/// "features:evaluations\n"
+pub fn format_memo_line<K: Display + ?Sized>(key: &K, value: &[f64]) -> String {
+    format!("{}:{}\n", key, value.iter().join(","))
+}
+
+/// "#fingerprint:<fingerprint>\n", only as the first line of a memo file
+pub fn format_fingerprint_line(fingerprint: &str) -> String {
+    format!("{}{}\n", FINGERPRINT_PREFIX, fingerprint)
+}
+
+pub fn parse_fingerprint_line(line: &str) -> Option<&str> {
+    line.strip_prefix(FINGERPRINT_PREFIX)
+}
+
+const FINGERPRINT_PREFIX: &str = "#fingerprint:";
+
+/// "#keying:<keying>\n", after the fingerprint line
+pub fn format_keying_line(keying: MemoKeying) -> String {
+    let name = match keying.written() {
+        MemoKeying::Hash128 => "hash128",
+        _ => "joined",
+    };
+    format!("{}{}\n", KEYING_PREFIX, name)
+}
+
+pub fn parse_keying_line(line: &str) -> Option<MemoKeying> {
+    match line.strip_prefix(KEYING_PREFIX)? {
+        "joined" => Some(MemoKeying::Joined),
+        "hash128" => Some(MemoKeying::Hash128),
+        _ => None,
+    }
+}
+
+const KEYING_PREFIX: &str = "#keying:";
+
+/// Fingerprint and keying lines. Joined keys have no keying line, same as older memo files.
+pub fn format_memo_header(fingerprint: Option<&str>, keying: MemoKeying) -> String {
+    let mut header = String::new();
+    if let Some(fingerprint) = fingerprint {
+        header += &format_fingerprint_line(fingerprint);
+    }
+    if keying.written() != MemoKeying::Joined {
+        header += &format_keying_line(keying);
+    }
+    header
+}
+
+/// Keying recorded in the leading '#' lines of a memo file
+pub fn parse_memo_keying(contents: &str) -> MemoKeying {
+    contents
+        .lines()
+        .take_while(|line| line.starts_with('#'))
+        .find_map(parse_keying_line)
+        .unwrap_or_default()
+}
+
+/// `key` written with `from`, as a key written with `to`.
+/// Hashed keys cannot be turned back into features, so Hash128 -> Joined panics.
+pub fn convert_memo_key(key: String, from: MemoKeying, to: MemoKeying) -> String {
+    match (from.written(), to.written()) {
+        (MemoKeying::Joined, MemoKeying::Hash128) => format_hashed_key(hash_joined_key(&key)),
+        (MemoKeying::Hash128, MemoKeying::Joined) => {
+            panic!("hashed memo keys cannot be read with MemoKeying::Joined")
+        }
+        _ => key,
+    }
+}
+
+/// Lines starting with '#' are not entries.
+pub fn parse_memo_line(line: &str) -> Option<(String, Vec<f64>)> {
+    if line.starts_with('#') {
+        return None;
+    }
+    let (key, value) = line.split_once(':')?;
+    if value.contains(':') {
+        return None;
+    }
+    let value = value
+        .split(',')
+        .map(f64::from_str)
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    Some((key.to_string(), value))
+}
+
+/// Append-only file that receives every new memo entry as soon as it is evaluated.
+///
+/// The format is the same as `save_memo_to_csv`, so a log can also be read by `load_memo_from_csv`.
+#[derive(Debug)]
+pub struct MemoLog {
+    path: String,
+    file: File,
+}
+
+impl MemoLog {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The whole line is written by a single `write_all`, so a crash leaves at most one torn line.
+    pub fn append<K: Display + ?Sized>(&mut self, key: &K, value: &[f64]) {
+        self.file
+            .write_all(format_memo_line(key, value).as_bytes())
+            .unwrap();
+    }
+
+    /// Replaces the whole log through a temporary file, so a crash leaves either log intact.
+    pub(crate) fn rewrite(&mut self, contents: &str) {
+        let tmp_path = format!("{}.compact", self.path);
+        let mut tmp = File::create(&tmp_path).unwrap();
+        tmp.write_all(contents.as_bytes()).unwrap();
+        tmp.sync_all().unwrap();
+        rename(&tmp_path, &self.path).unwrap();
+        self.file = OpenOptions::new().append(true).open(&self.path).unwrap();
+    }
+
+    /// Rewrites the keys of the log (and its keying line) for `to`.
+    pub(crate) fn convert_keys(&mut self, fingerprint: Option<&str>, to: MemoKeying) {
+        let buf = std::fs::read_to_string(&self.path).unwrap();
+        let from = parse_memo_keying(&buf);
+        if from == to.written() {
+            return;
+        }
+        let mut contents = format_memo_header(fingerprint, to);
+        for (key, value) in buf.lines().filter_map(parse_memo_line) {
+            contents += &format_memo_line(&convert_memo_key(key, from, to), &value);
+        }
+        self.rewrite(&contents);
+    }
+}
+
+pub trait ExtMemoLog<I> {
+    /// Opens (or creates) the log, loads its entries into the memo and appends from now on.
+    /// A log written with another fingerprint is left untouched and not opened.
+    fn open_memo_log(&mut self, path: &str) -> Result<(), group::FingerprintMismatch>;
+    /// Rewrites the log so that it holds exactly one line per memo entry.
+    /// With a `MemoBound` the memo may have evicted entries, so the log keeps one line per key
+    /// it holds instead (the latest one).
+    fn compact_memo_log(&mut self);
+    fn close_memo_log(&mut self);
+}
+
+impl<I, G> ExtMemoLog<I> for G
+where
+    I: individual::ExtMinimum,
+    G: group::ExtMemoization<I>,
+{
+    fn open_memo_log(&mut self, path: &str) -> Result<(), group::FingerprintMismatch> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .unwrap();
+        let mut buf = String::new();
+        file.read_to_string(&mut buf).unwrap();
+
+        // 書き込み途中で落ちた最後の行は捨てる
+        let complete_len = buf.rfind('\n').map_or(0, |i| i + 1);
+        if complete_len > 0 {
+            let found = buf.lines().next().and_then(parse_fingerprint_line);
+            group::check_fingerprint(self.get_fingerprint(), found)?;
+        }
+        if complete_len != buf.len() {
+            file.set_len(complete_len as u64).unwrap();
+        }
+        let keying = *self.memo_keying_as_ref();
+        if complete_len == 0 {
+            file.write_all(format_memo_header(self.get_fingerprint(), keying).as_bytes())
+                .unwrap();
+        }
+        let written = parse_memo_keying(&buf[..complete_len]);
+        for line in buf[..complete_len].lines() {
+            if let Some((key, value)) = parse_memo_line(line) {
+                let key = self.read_memo_key(key, written);
+                self.store_memo(key, value);
+            }
+        }
+
+        let mut log = MemoLog {
+            path: path.to_string(),
+            file,
+        };
+        // これから追記するkeyと揃える
+        log.convert_keys(self.get_fingerprint(), keying);
+        *self.memo_log_as_mut() = Some(log);
+        Ok(())
+    }
+
+    fn compact_memo_log(&mut self) {
+        let Some(mut log) = self.memo_log_as_mut().take() else {
+            return;
+        };
+        let mut entries: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        if self.memo_cache_as_ref().bound().is_some() {
+            let written = std::fs::read_to_string(log.path()).unwrap();
+            entries.extend(written.lines().filter_map(parse_memo_line));
+        }
+        entries.extend(
+            self.memo_as_ref()
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone())),
+        );
+        let mut buf = format_memo_header(self.get_fingerprint(), *self.memo_keying_as_ref());
+        for (key, value) in entries.iter() {
+            buf += &format_memo_line(key, value);
+        }
+        log.rewrite(&buf);
+        *self.memo_log_as_mut() = Some(log);
+    }
+
+    fn close_memo_log(&mut self) {
+        if let Some(log) = self.memo_log_as_mut().take() {
+            log.file.sync_all().unwrap();
+        }
+    }
+}
+
+/// How `memo_key` turns features into a memo key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemoKeying {
+    /// "f1,f2,..." (compatible with memo files written by older versions)
+    #[default]
+    Joined,
+    /// 128-bit FNV-1a of the joined string, written as 32 hex digits.
+    /// Features are streamed into the hash, so no joined string is built.
+    Hash128,
+    /// The features themselves, hashed with `Hash`. Set by
+    /// `ExtFeatureKeying::set_feature_memo_keying` (`Feature: Hash + Eq`); written as Joined.
+    Features,
+}
+
+impl MemoKeying {
+    /// keying of the keys in memo files
+    pub fn written(self) -> MemoKeying {
+        match self {
+            MemoKeying::Features => MemoKeying::Joined,
+            keying => keying,
+        }
+    }
+}
+
+/// A memo key. `Display` gives the key written to memo files.
+#[derive(Debug, Clone)]
+pub enum MemoKey<F> {
+    Joined(String),
+    Hash128(u128),
+    /// (features, their hash by the group's feature hasher)
+    Features(Vec<F>, u64),
+}
+
+impl<F> MemoKey<F> {
+    pub fn keying(&self) -> MemoKeying {
+        match self {
+            MemoKey::Joined(_) => MemoKeying::Joined,
+            MemoKey::Hash128(_) => MemoKeying::Hash128,
+            MemoKey::Features(..) => MemoKeying::Features,
+        }
+    }
+}
+
+impl<F: PartialEq> PartialEq for MemoKey<F> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (MemoKey::Joined(a), MemoKey::Joined(b)) => a == b,
+            (MemoKey::Hash128(a), MemoKey::Hash128(b)) => a == b,
+            (MemoKey::Features(a, x), MemoKey::Features(b, y)) => x == y && a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Features keys are only made for `Feature: Eq`.
+impl<F: PartialEq> Eq for MemoKey<F> {}
+
+impl<F> Hash for MemoKey<F> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            MemoKey::Joined(key) => key.hash(state),
+            MemoKey::Hash128(hash) => hash.hash(state),
+            MemoKey::Features(_, hash) => hash.hash(state),
+        }
+    }
+}
+
+impl<F: Display> Display for MemoKey<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemoKey::Joined(key) => f.write_str(key),
+            MemoKey::Hash128(hash) => write!(f, "{:032x}", hash),
+            MemoKey::Features(features, _) => write!(f, "{}", features.iter().format(",")),
+        }
+    }
+}
+
+/// A key of a memo file written with `written`; converted by `ExtMemoization::read_memo_key`.
+pub fn parse_written_key<F>(key: String, written: MemoKeying) -> MemoKey<F> {
+    match written {
+        MemoKeying::Hash128 => MemoKey::Hash128(
+            u128::from_str_radix(&key, 16)
+                .unwrap_or_else(|_| panic!("invalid hashed memo key {:?}", key)),
+        ),
+        _ => MemoKey::Joined(key),
+    }
+}
+
+/// Features of a joined key; `None` if one does not parse
+pub fn parse_joined_key<F: FromStr>(key: &str) -> Option<Vec<F>> {
+    if key.is_empty() {
+        return Some(vec![]);
+    }
+    key.split(',').map(|x| F::from_str(x).ok()).collect()
+}
+
+/// Hash of the features of `MemoKeying::Features`
+pub type FeatureHash<F> = fn(&[F]) -> u64;
+
+fn hash_feature_vec<F: Hash>(features: &[F]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    features.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Ties of the same keying are ordered like their written keys.
+fn compare_keys<F: Display>(a: &MemoKey<F>, b: &MemoKey<F>) -> Ordering {
+    match (a, b) {
+        (MemoKey::Joined(a), MemoKey::Joined(b)) => a.cmp(b),
+        (MemoKey::Hash128(a), MemoKey::Hash128(b)) => a.cmp(b),
+        _ => a.to_string().cmp(&b.to_string()),
+    }
+}
+
+pub trait ExtFeatureKeying<I> {
+    /// `MemoKeying::Features`, converting the memo like `set_memo_keying`
+    fn set_feature_memo_keying(&mut self);
+}
+
+impl<I, G> ExtFeatureKeying<I> for G
+where
+    I: individual::ExtMinimum,
+    I::Feature: Hash + Eq,
+    G: group::ExtMemoization<I>,
+{
+    fn set_feature_memo_keying(&mut self) {
+        *self.memo_hasher_as_mut() = Some(hash_feature_vec::<I::Feature>);
+        self.set_memo_keying(MemoKeying::Features);
+    }
+}
+
+/// Stable 128-bit FNV-1a. Unlike `DefaultHasher` the result does not change between runs,
+/// so hashed keys can be written to memo files.
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv128(u128);
+
+impl Fnv128 {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013B;
+
+    pub fn new() -> Self {
+        Self(Self::OFFSET)
+    }
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u128;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+    pub fn finish(&self) -> u128 {
+        self.0
+    }
+}
+
+impl Default for Fnv128 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Write for Fnv128 {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Streamed through `Display`, so that it agrees with `hash_joined_key` of memo files.
+pub fn hash_features<F: std::fmt::Display>(features: &[F]) -> u128 {
+    use std::fmt::Write as _;
+    let mut hasher = Fnv128::new();
+    for (i, feature) in features.iter().enumerate() {
+        if i != 0 {
+            hasher.write(b",");
+        }
+        write!(hasher, "{}", feature).unwrap();
+    }
+    hasher.finish()
+}
+
+/// A joined key and its hashed key always agree: `hash_joined_key("1,2") == hash_features(&[1, 2])`.
+pub fn hash_joined_key(key: &str) -> u128 {
+    let mut hasher = Fnv128::new();
+    hasher.write(key.as_bytes());
+    hasher.finish()
+}
+
+pub fn format_hashed_key(hash: u128) -> String {
+    format!("{:032x}", hash)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    /// least recently used
+    Lru,
+    /// least frequently used (ties: least recently used)
+    Lfu,
+}
+
+/// Upper limits of the memo. Entries over the limits are evicted, so an evicted feature is
+/// simply evaluated again the next time it appears.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoBound {
+    pub capacity: Option<usize>,
+    /// estimated by `MemoCache::entry_bytes`
+    pub byte_budget: Option<usize>,
+    pub eviction: Eviction,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub insertions: usize,
+    pub evictions: usize,
+}
+
+/// Bookkeeping for `MemoBound`. It only tracks keys; the values stay in the memo itself.
+#[derive(Debug)]
+pub struct MemoCache<F> {
+    bound: Option<MemoBound>,
+    stats: MemoStats,
+    tick: u64,
+    bytes: usize,
+    /// key -> (last used tick, use count, bytes)
+    usage: HashMap<MemoKey<F>, (u64, u64, usize)>,
+    /// (priority, last used tick) -> key, the first entry is evicted first
+    order: BTreeMap<(u64, u64), MemoKey<F>>,
+}
+
+impl<F> Default for MemoCache<F> {
+    fn default() -> Self {
+        Self {
+            bound: None,
+            stats: MemoStats::default(),
+            tick: 0,
+            bytes: 0,
+            usage: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+}
+
+impl<F> MemoCache<F>
+where
+    F: Clone + PartialEq + Display,
+{
+    const ENTRY_OVERHEAD: usize = 64;
+
+    pub fn entry_bytes(key: &MemoKey<F>, value: &[f64]) -> usize {
+        let key_bytes = match key {
+            MemoKey::Joined(key) => key.len(),
+            MemoKey::Hash128(hash) => std::mem::size_of_val(hash),
+            MemoKey::Features(features, hash) => {
+                std::mem::size_of_val(features.as_slice()) + std::mem::size_of_val(hash)
+            }
+        };
+        key_bytes + std::mem::size_of_val(value) + Self::ENTRY_OVERHEAD
+    }
+    pub fn bound(&self) -> Option<MemoBound> {
+        self.bound
+    }
+    pub fn stats(&self) -> MemoStats {
+        self.stats
+    }
+    /// estimated size of the tracked entries
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub(crate) fn set_bound(&mut self, bound: Option<MemoBound>, memo: &group::Memo<F>) {
+        self.bound = bound;
+        self.usage.clear();
+        self.order.clear();
+        self.bytes = 0;
+        if bound.is_some() {
+            for (key, value) in memo.iter().sorted_by(|a, b| compare_keys(a.0, b.0)) {
+                self.track_usage(key, value);
+            }
+        }
+    }
+
+    /// Forgets every entry; the bound and the stats are kept.
+    pub(crate) fn clear(&mut self) {
+        self.usage.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+
+    fn priority(&self, last_used: u64, count: u64) -> u64 {
+        match self.bound.map(|b| b.eviction) {
+            Some(Eviction::Lfu) => count,
+            _ => last_used,
+        }
+    }
+
+    pub(crate) fn hit(&mut self, key: &MemoKey<F>) {
+        self.stats.hits += 1;
+        if self.bound.is_none() {
+            return;
+        }
+        self.tick += 1;
+        if let Some((last_used, count, bytes)) = self.usage.get(key).copied() {
+            let priority = self.priority(last_used, count);
+            let key = self.order.remove(&(priority, last_used)).unwrap();
+            self.order.insert(
+                (self.priority(self.tick, count + 1), self.tick),
+                key.clone(),
+            );
+            self.usage.insert(key, (self.tick, count + 1, bytes));
+        }
+    }
+
+    pub(crate) fn miss(&mut self) {
+        self.stats.misses += 1;
+    }
+
+    pub(crate) fn track(&mut self, key: &MemoKey<F>, value: &[f64]) {
+        self.stats.insertions += 1;
+        self.track_usage(key, value);
+    }
+
+    fn track_usage(&mut self, key: &MemoKey<F>, value: &[f64]) {
+        if self.bound.is_none() {
+            return;
+        }
+        self.untrack(key);
+        self.tick += 1;
+        let bytes = Self::entry_bytes(key, value);
+        self.bytes += bytes;
+        self.order
+            .insert((self.priority(self.tick, 1), self.tick), key.clone());
+        self.usage.insert(key.clone(), (self.tick, 1, bytes));
+    }
+
+    pub(crate) fn untrack(&mut self, key: &MemoKey<F>) {
+        if let Some((last_used, count, bytes)) = self.usage.remove(key) {
+            let priority = self.priority(last_used, count);
+            self.order.remove(&(priority, last_used));
+            self.bytes -= bytes;
+        }
+    }
+
+    /// The same entry under a key of another keying; its usage is kept.
+    pub(crate) fn rekey(&mut self, from: &MemoKey<F>, to: &MemoKey<F>, value: &[f64]) {
+        if let Some((last_used, count, bytes)) = self.usage.remove(from) {
+            let new_bytes = Self::entry_bytes(to, value);
+            self.bytes = self.bytes - bytes + new_bytes;
+            self.order
+                .insert((self.priority(last_used, count), last_used), to.clone());
+            self.usage.insert(to.clone(), (last_used, count, new_bytes));
+        }
+    }
+
+    /// Returns the keys to remove from the memo after inserting `inserted`, which is never evicted.
+    pub(crate) fn evict_for(&mut self, inserted: &MemoKey<F>) -> Vec<MemoKey<F>> {
+        self.evict(Some(inserted))
+    }
+
+    /// Returns the keys to remove from the memo to get within the bound, e.g. after it was lowered.
+    pub(crate) fn enforce_bound(&mut self) -> Vec<MemoKey<F>> {
+        self.evict(None)
+    }
+
+    fn evict(&mut self, keep: Option<&MemoKey<F>>) -> Vec<MemoKey<F>> {
+        let bound = match self.bound {
+            Some(bound) => bound,
+            None => return vec![],
+        };
+        let mut evicted = vec![];
+        loop {
+            let over_capacity = bound.capacity.is_some_and(|c| self.usage.len() > c);
+            let over_budget = bound.byte_budget.is_some_and(|b| self.bytes > b);
+            if !over_capacity && !over_budget {
+                break;
+            }
+            let victim = self.order.values().find(|key| Some(*key) != keep).cloned();
+            match victim {
+                Some(key) => {
+                    self.untrack(&key);
+                    self.stats.evictions += 1;
+                    evicted.push(key);
+                }
+                None => break,
+            }
+        }
+        evicted
+    }
+}
+
+/// larger evaluations are better, same as `get_best`
+fn compare_entries<F: Display>(
+    a: &(&MemoKey<F>, &Vec<f64>),
+    b: &(&MemoKey<F>, &Vec<f64>),
+) -> Ordering {
+    b.1.partial_cmp(a.1)
+        .unwrap()
+        .then_with(|| compare_keys(a.0, b.0))
+}
+
+fn dominates(a: &[f64], b: &[f64]) -> bool {
+    a.iter().zip(b).all(|(x, y)| x >= y) && a.iter().zip(b).any(|(x, y)| x > y)
+}
+
+pub trait ExtMemoQuery<I>
+where
+    I: individual::Minimum,
+{
+    /// Best `k` entries, best first, without sorting the whole memo.
+    fn top_memo(&self, k: usize) -> Vec<(MemoKey<I::Feature>, Vec<f64>)>;
+    /// Entries whose parsed features and evaluations satisfy `predicate`.
+    /// Keys that cannot be parsed (e.g. `MemoKeying::Hash128`) are skipped.
+    fn filter_memo<P>(&self, predicate: P) -> Vec<(Vec<I::Feature>, Vec<f64>)>
+    where
+        P: FnMut(&[I::Feature], &[f64]) -> bool;
+    /// Entries not dominated by any other entry (every evaluation is maximized).
+    fn pareto_memo(&self) -> Vec<(MemoKey<I::Feature>, Vec<f64>)>;
+    fn get_memo<'a>(&'a self, features: &[I::Feature]) -> Option<&'a Vec<f64>>
+    where
+        I::Feature: 'a;
+    /// `None` for hashed keys
+    fn parse_memo_key(&self, key: &MemoKey<I::Feature>) -> Option<Vec<I::Feature>>;
+}
+
+impl<I, G> ExtMemoQuery<I> for G
+where
+    I: individual::ExtMinimum,
+    G: group::ExtMemoization<I>,
+{
+    fn top_memo(&self, k: usize) -> Vec<(MemoKey<I::Feature>, Vec<f64>)> {
+        if k == 0 {
+            return vec![];
+        }
+        let mut entries = self.memo_as_ref().iter().collect_vec();
+        if k < entries.len() {
+            entries.select_nth_unstable_by(k - 1, compare_entries);
+            entries.truncate(k);
+        }
+        entries.sort_by(compare_entries);
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    fn filter_memo<P>(&self, mut predicate: P) -> Vec<(Vec<I::Feature>, Vec<f64>)>
+    where
+        P: FnMut(&[I::Feature], &[f64]) -> bool,
+    {
+        let mut filtered = vec![];
+        for (key, value) in self
+            .memo_as_ref()
+            .iter()
+            .sorted_by(|a, b| compare_keys(a.0, b.0))
+        {
+            if let Some(features) = self.parse_memo_key(key) {
+                if predicate(&features, value) {
+                    filtered.push((features, value.clone()));
+                }
+            }
+        }
+        filtered
+    }
+
+    fn pareto_memo(&self) -> Vec<(MemoKey<I::Feature>, Vec<f64>)> {
+        let mut front: Vec<(&MemoKey<I::Feature>, &Vec<f64>)> = vec![];
+        for entry in self.memo_as_ref().iter().sorted_by(compare_entries) {
+            // 辞書順で後ろのものが前のものを支配することはない
+            if !front.iter().any(|f| dominates(f.1, entry.1)) {
+                front.push(entry);
+            }
+        }
+        front
+            .into_iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    fn get_memo<'a>(&'a self, features: &[I::Feature]) -> Option<&'a Vec<f64>>
+    where
+        I::Feature: 'a,
+    {
+        self.memo_as_ref().get(&self.memo_key(features))
+    }
+
+    fn parse_memo_key(&self, key: &MemoKey<I::Feature>) -> Option<Vec<I::Feature>> {
+        match key {
+            MemoKey::Joined(key) => parse_joined_key(key),
+            MemoKey::Hash128(_) => None,
+            MemoKey::Features(features, _) => Some(features.clone()),
+        }
+    }
+}
+
+/// What to do when sources disagree on the evaluations of the same feature key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    KeepFirst,
+    KeepLatest,
+    /// Component-wise mean of the merged memos holding the key. The current value is replaced,
+    /// not averaged in, so merging the same memos again gives the same value.
+    /// Keys whose merged evaluations differ in length are left as they are and reported in
+    /// `MergeReport::unmerged`.
+    Average,
+    Fail,
+}
+
+/// The same key with different evaluations: the evaluation is noisy or has been changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoConflict {
+    pub key: String,
+    /// distinct evaluations in source order (the current memo first)
+    pub evaluations: Vec<Vec<f64>>,
+}
+
+impl Display for MemoConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "memo conflict on \"{}\": {:?}",
+            self.key, self.evaluations
+        )
+    }
+}
+
+impl Error for MemoConflict {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeError {
+    /// with `MergePolicy::Fail`
+    Conflict(MemoConflict),
+    /// a memo file or group written with another fingerprint
+    Fingerprint(group::FingerprintMismatch),
+}
+
+impl Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeError::Conflict(conflict) => conflict.fmt(f),
+            MergeError::Fingerprint(mismatch) => mismatch.fmt(f),
+        }
+    }
+}
+
+impl Error for MergeError {}
+
+impl From<MemoConflict> for MergeError {
+    fn from(conflict: MemoConflict) -> Self {
+        MergeError::Conflict(conflict)
+    }
+}
+
+impl From<group::FingerprintMismatch> for MergeError {
+    fn from(mismatch: group::FingerprintMismatch) -> Self {
+        MergeError::Fingerprint(mismatch)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MergeReport {
+    pub added: usize,
+    pub updated: usize,
+    pub conflicts: Vec<MemoConflict>,
+    /// `MergePolicy::Average` over evaluations of different lengths; these keys are not changed
+    pub unmerged: Vec<MemoConflict>,
+}
+
+/// Entries of a memo file with their written keys
+pub fn read_memo_file(input_path: &str) -> HashMap<String, Vec<f64>> {
+    let mut memo = HashMap::new();
+    for result in BufReader::new(File::open(input_path).unwrap()).lines() {
+        let line = result.unwrap();
+        if line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = parse_memo_line(&line).unwrap();
+        memo.insert(key, value);
+    }
+    memo
+}
+
+pub fn read_memo_keying(input_path: &str) -> MemoKeying {
+    parse_memo_keying(&std::fs::read_to_string(input_path).unwrap())
+}
+
+pub fn read_memo_fingerprint(input_path: &str) -> Option<String> {
+    let mut first_line = String::new();
+    BufReader::new(File::open(input_path).unwrap())
+        .read_line(&mut first_line)
+        .unwrap();
+    parse_fingerprint_line(first_line.trim_end_matches('\n')).map(str::to_string)
+}
+
+pub trait ExtMemoMerge<I>
+where
+    I: individual::Minimum,
+{
+    /// Merges `memos` (of the same `MemoKeying`) into the own memo.
+    /// With `MergePolicy::Fail` nothing is merged on a conflict.
+    /// Bare memos carry no fingerprint; `merge_group_memos` checks the groups' ones.
+    fn merge_memos(
+        &mut self,
+        memos: &[&group::Memo<I::Feature>],
+        policy: MergePolicy,
+    ) -> Result<MergeReport, MergeError>;
+    /// Nothing is merged if one of the groups has another fingerprint.
+    fn merge_group_memos(
+        &mut self,
+        groups: &[&Self],
+        policy: MergePolicy,
+    ) -> Result<MergeReport, MergeError>
+    where
+        Self: Sized;
+    /// Nothing is merged if one of the files has another fingerprint.
+    fn merge_memo_files(
+        &mut self,
+        input_paths: &[&str],
+        policy: MergePolicy,
+    ) -> Result<MergeReport, MergeError>;
+}
+
+impl<I, G> ExtMemoMerge<I> for G
+where
+    I: individual::ExtMinimum,
+    G: group::ExtMemoization<I>,
+{
+    fn merge_memos(
+        &mut self,
+        memos: &[&group::Memo<I::Feature>],
+        policy: MergePolicy,
+    ) -> Result<MergeReport, MergeError> {
+        let mut candidates: HashMap<&MemoKey<I::Feature>, Vec<&Vec<f64>>> = HashMap::new();
+        for memo in memos {
+            for (key, value) in memo.iter() {
+                candidates
+                    .entry(key)
+                    .or_insert_with(|| self.memo_as_ref().get(key).into_iter().collect())
+                    .push(value);
+            }
+        }
+        let candidates = candidates
+            .into_iter()
+            .sorted_by(|a, b| compare_keys(a.0, b.0));
+
+        let mut report = MergeReport::default();
+        let mut merged = vec![];
+        for (key, values) in candidates {
+            let mut distinct: Vec<Vec<f64>> = vec![];
+            for value in values.iter() {
+                if !distinct.contains(value) {
+                    distinct.push(value.to_vec());
+                }
+            }
+            if distinct.len() > 1 {
+                let conflict = MemoConflict {
+                    key: key.to_string(),
+                    evaluations: distinct,
+                };
+                if policy == MergePolicy::Fail {
+                    return Err(conflict.into());
+                }
+                report.conflicts.push(conflict);
+            }
+
+            // 現在の値は平均に含めない. 含めると同じmemoをmergeするたびに値が動く
+            let inputs = if self.memo_as_ref().contains_key(key) {
+                &values[1..]
+            } else {
+                &values[..]
+            };
+            let value = match policy {
+                MergePolicy::KeepFirst | MergePolicy::Fail => values[0].clone(),
+                MergePolicy::KeepLatest => values[values.len() - 1].clone(),
+                MergePolicy::Average if inputs.iter().any(|v| v.len() != inputs[0].len()) => {
+                    report.unmerged.push(MemoConflict {
+                        key: key.to_string(),
+                        evaluations: inputs.iter().map(|v| v.to_vec()).collect(),
+                    });
+                    continue;
+                }
+                MergePolicy::Average => (0..inputs[0].len())
+                    .map(|i| inputs.iter().map(|v| v[i]).sum::<f64>() / inputs.len() as f64)
+                    .collect(),
+            };
+            match self.memo_as_ref().get(key) {
+                None => report.added += 1,
+                Some(current) if *current != value => report.updated += 1,
+                Some(_) => continue,
+            }
+            merged.push((key.clone(), value));
+        }
+
+        for (key, value) in merged {
+            self.insert_memo(key, value);
+        }
+        Ok(report)
+    }
+
+    fn merge_group_memos(
+        &mut self,
+        groups: &[&Self],
+        policy: MergePolicy,
+    ) -> Result<MergeReport, MergeError> {
+        for group in groups {
+            group::check_fingerprint(self.get_fingerprint(), group.get_fingerprint())?;
+        }
+        let memos = groups.iter().map(|g| g.memo_as_ref()).collect_vec();
+        self.merge_memos(&memos, policy)
+    }
+
+    fn merge_memo_files(
+        &mut self,
+        input_paths: &[&str],
+        policy: MergePolicy,
+    ) -> Result<MergeReport, MergeError> {
+        for path in input_paths {
+            let found = read_memo_fingerprint(path);
+            group::check_fingerprint(self.get_fingerprint(), found.as_deref())?;
+        }
+        let memos = input_paths
+            .iter()
+            .map(|path| {
+                let written = read_memo_keying(path);
+                read_memo_file(path)
+                    .into_iter()
+                    .map(|(key, value)| (self.read_memo_key(key, written), value))
+                    .collect::<group::Memo<I::Feature>>()
+            })
+            .collect_vec();
+        self.merge_memos(&memos.iter().collect_vec(), policy)
+    }
+}
+
+pub trait ExtMemoSeed<I>
+where
+    I: individual::Minimum,
+{
+    /// Replaces the last individuals with the best `k` memo entries mapped back to genes by
+    /// `individual::Minimum::encode`. Returns the number of seeded individuals
+    /// (unparsable keys and features that cannot be encoded are skipped).
+    fn seed_from_memo(&mut self, k: usize) -> usize;
+    /// Same as `seed_from_memo`, with `encode` instead of the individual's encode.
+    fn seed_from_memo_with<E>(&mut self, k: usize, encode: E) -> usize
+    where
+        E: Fn(&[I::Feature]) -> Option<Vec<f64>>;
+}
+
+impl<I, G> ExtMemoSeed<I> for G
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::ExtMemoization<I>,
+{
+    fn seed_from_memo(&mut self, k: usize) -> usize {
+        self.seed_from_memo_with(k, I::encode)
+    }
+
+    fn seed_from_memo_with<E>(&mut self, k: usize, encode: E) -> usize
+    where
+        E: Fn(&[I::Feature]) -> Option<Vec<f64>>,
+    {
+        let len = self.get_individuals().len();
+        let seeds = self
+            .top_memo(k.min(len))
+            .iter()
+            .filter_map(|(key, _)| self.parse_memo_key(key))
+            .filter_map(|features| {
+                let genes = encode(&features)?
+                    .iter()
+                    .map(|g| g.clamp(0.0, 1.0))
+                    .collect();
+                let mut individual = I::from_genes(genes);
+                individual.set_features(individual.identificate());
+                Some(individual)
+            })
+            .collect_vec();
+
+        let mut individuals = self.get_individuals().clone();
+        let seeded = seeds.len();
+        for (i, seed) in seeds.into_iter().enumerate() {
+            individuals[len - seeded + i] = seed;
+        }
+        self.set_individuals(individuals);
+        seeded
+    }
+}
@@ -1,9 +1,166 @@
+use std::cmp::Ordering;
 use std::fmt::Debug;
 
-use itertools::Itertools;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
+use crate::dynamic::{self, ExtDynamic};
+use crate::evaluator::{self, ExtBatchEvaluation};
 use crate::group;
+use crate::hall_of_fame::ExtHallOfFame;
 use crate::individual;
+use crate::noise::{self, ExtNoise};
+use crate::polish;
+use crate::robust;
+use crate::surrogate::{self, ExtSurrogate};
+
+/// advance_epochの挙動を切り替える設定. Groupに保持され、`options_as_mut`で変更する
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    /// Opposition-based generation jumping. After each generation, with this probability,
+    /// the opposite points (lo + hi - gene, with the bounds of the current population) are
+    /// evaluated and the best individuals of both are kept.
+    pub jumping_rate: Option<f64>,
+    /// Opposition-based initialization. Before the first generation the opposite points
+    /// (1.0 - gene) of the initial individuals are evaluated too, and the better half is kept.
+    pub initial_opposition: bool,
+    pub updating: Updating,
+    pub restart: Option<Restart>,
+    pub niching: Niching,
+    /// distance used by niching
+    pub niching_space: Space,
+    pub memetic: Option<Memetic>,
+    pub surrogate: Option<surrogate::Surrogate>,
+    pub guard: Option<evaluator::Guard>,
+    /// cannot be combined with `robustness`
+    pub noise: Option<noise::Noise>,
+    pub robustness: Option<robust::Robustness>,
+    pub dynamic: Option<dynamic::Dynamic>,
+}
+
+/// Local search on the best individuals every `interval` epochs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Memetic {
+    pub interval: usize,
+    /// how many of the best individuals are improved
+    pub top: usize,
+    pub search: polish::Polish,
+    /// evaluations per individual, memo hits included
+    pub max_evaluations: usize,
+    pub write_back: WriteBack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteBack {
+    /// the improved individual replaces the original
+    #[default]
+    Lamarckian,
+    /// only the improved evaluations are kept; the genes stay as they were
+    Baldwinian,
+}
+
+/// Niching keeps several optima in the population instead of converging to one.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Niching {
+    #[default]
+    None,
+    /// a trial competes with its nearest neighbour instead of its parent
+    Crowding,
+    /// individuals within `radius` of a better species seed form a species,
+    /// and mutations only combine members of the own species
+    Speciation { radius: f64 },
+    /// selection uses the first evaluation shared among the neighbours within `radius`
+    /// (sh(d) = 1 - (d / radius)^alpha). If the population has negative evaluations they are
+    /// shifted first, so that the worst one counts half as much as the best one.
+    Sharing { radius: f64, alpha: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Space {
+    #[default]
+    Genes,
+    /// features parsed as f64; features that are not numbers count 0 if equal and 1 otherwise
+    Features,
+}
+
+/// Euclidean distance in `space`
+pub fn distance<I: individual::Minimum>(a: &I, b: &I, space: Space) -> f64 {
+    let squared: f64 = match space {
+        Space::Genes => a
+            .get_genes()
+            .iter()
+            .zip(b.get_genes())
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum(),
+        Space::Features => a
+            .get_features()
+            .iter()
+            .zip(b.get_features())
+            .map(|(x, y)| {
+                let (x, y) = (x.to_string(), y.to_string());
+                match (x.parse::<f64>(), y.parse::<f64>()) {
+                    (Ok(x), Ok(y)) => (x - y) * (x - y),
+                    _ if x == y => 0.0,
+                    _ => 1.0,
+                }
+            })
+            .sum(),
+    };
+    squared.sqrt()
+}
+
+/// Restart from new random individuals when the population has converged (IPOP when
+/// `population_growth` > 1.0). The best individual and the memo are kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Restart {
+    /// restart when the widest gene range of the population is below this
+    pub gene_spread: f64,
+    /// restart when the range of the first evaluation is below this
+    pub evaluation_spread: f64,
+    pub population_growth: f64,
+    pub max_population: usize,
+}
+
+/// Run history, kept in the group and saved with it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct History {
+    /// epochs advanced so far
+    pub epochs: usize,
+    pub restarts: Vec<RestartRecord>,
+    /// evaluations spent by the memetic local search
+    #[serde(default)]
+    pub local_search_evaluations: usize,
+    /// evaluations that failed under `Options::guard`
+    #[serde(default)]
+    pub failures: Vec<evaluator::FailureRecord>,
+    /// environment changes found by `Options::dynamic`
+    #[serde(default)]
+    pub changes: Vec<dynamic::ChangeRecord>,
+    /// `FailurePolicy::Abort` has seen too many failures; advance_epoch does nothing more
+    #[serde(default)]
+    pub aborted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestartRecord {
+    pub epoch: usize,
+    pub gene_spread: f64,
+    pub evaluation_spread: f64,
+    /// population size after the restart
+    pub population_len: usize,
+    pub best_evaluations: Vec<f64>,
+}
+
+/// When a winning trial replaces its parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Updating {
+    /// at the end of the generation; trials are made from a frozen population
+    #[default]
+    Deferred,
+    /// at once, so later mutations of the same generation already use it (steady-state DE)
+    Immediate,
+}
+
 pub trait ExtDefaultDE<I> {
     fn advance_epoch(
         &mut self,
@@ -30,11 +187,8 @@ where
     ) {
         let mut tmp_individuals = self.get_individuals().clone();
         for individual in tmp_individuals.iter_mut() {
-            if individual.get_evaluations().len() == 0 {
+            if individual.get_evaluations().is_empty() {
                 individual.set_features(individual.identificate());
-                individual.set_evaluations(vec![]);
-            }
-            if individual.get_evaluations().len() == 0 {
                 individual.set_evaluations(individual.evaluate());
             }
         }
@@ -42,14 +196,11 @@ where
 
         for _ in 0..epoch {
             let pre_individuals = self.get_individuals().clone();
-            let mut next_individuals = Vec::with_capacity(self.get_individuals().len());
+            let mut next_individuals = Vec::with_capacity(pre_individuals.len());
             for individual in pre_individuals {
                 let mutant = self.de_mutate(best_or_rand, difference_vector_count, f_scale);
-                let mut trial = individual.cross(
-                    &mutant,
-                    crossover_rate,
-                    &mut self.borrowed_random_generator(),
-                );
+                let mut trial =
+                    individual.cross(&mutant, crossover_rate, self.borrowed_random_generator());
 
                 trial.set_evaluations(trial.evaluate());
 
@@ -65,6 +216,70 @@ where
     }
 }
 
+/// ExtDefaultDE following `Options` (updating, niching, jumping, restart, memetic, guard,
+/// robustness), the batch evaluator, the run history and the hall of fame, without a memo.
+/// `Options::surrogate`, `noise` and `dynamic` need the memo of `ExtMemoizationDE` and panic here.
+pub trait ExtConfiguredDE<I> {
+    fn advance_epoch(
+        &mut self,
+        epoch: usize,
+        best_or_rand: &str,
+        difference_vector_count: usize,
+        f_scale: f64,
+        crossover_rate: f64,
+    );
+}
+
+impl<I, G> ExtConfiguredDE<I> for G
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::BaseDE<I>
+        + group::Configurable<I>
+        + group::RunHistory<I>
+        + group::BatchEvaluation<I>
+        + group::Fame<I>,
+{
+    fn advance_epoch(
+        &mut self,
+        epoch: usize,
+        best_or_rand: &str,
+        difference_vector_count: usize,
+        f_scale: f64,
+        crossover_rate: f64,
+    ) {
+        // 予測に使うmemoがない
+        assert!(
+            self.options_as_ref().surrogate.is_none(),
+            "Options::surrogate needs the memo of ExtMemoizationDE"
+        );
+        // sampleはmemo keyごとに持つ
+        assert!(
+            self.options_as_ref().noise.is_none(),
+            "Options::noise needs the memo of ExtMemoizationDE"
+        );
+        assert!(
+            self.options_as_ref().dynamic.is_none(),
+            "Options::dynamic needs the memo of ExtMemoizationDE"
+        );
+        evolve(
+            self,
+            epoch,
+            best_or_rand,
+            difference_vector_count,
+            f_scale,
+            crossover_rate,
+            |group, individuals| {
+                robust::evaluate_robust(group, individuals, &mut |group: &mut G, individuals| {
+                    group.evaluate_individuals(individuals);
+                    group.check_failures();
+                })
+            },
+            |_, _| 0,
+            |_, incumbent, trial| !incumbent.is_better_than(trial),
+        );
+    }
+}
+
 pub trait ExtMemoizationDE<I> {
     fn advance_epoch(
         &mut self,
@@ -79,7 +294,14 @@ pub trait ExtMemoizationDE<I> {
 impl<I, G> ExtMemoizationDE<I> for G
 where
     I: individual::ExtMinimum + Clone + Debug,
-    G: group::BaseDE<I> + group::ExtMemoization<I>,
+    G: group::BaseDE<I>
+        + group::ExtMemoization<I>
+        + group::Configurable<I>
+        + group::RunHistory<I>
+        + group::SurrogateModeling<I>
+        + group::BatchEvaluation<I>
+        + group::Sampling<I>
+        + group::Fame<I>,
 {
     fn advance_epoch(
         &mut self,
@@ -89,55 +311,624 @@ where
         f_scale: f64,
         crossover_rate: f64,
     ) {
-        let mut tmp_individuals = self.get_individuals().clone();
-        for individual in tmp_individuals.iter_mut() {
-            if individual.get_features().len() == 0 {
-                individual.set_features(individual.identificate());
-                individual.set_evaluations(vec![]);
+        assert!(
+            self.options_as_ref().noise.is_none() || self.options_as_ref().robustness.is_none(),
+            "noise and robustness cannot be combined"
+        );
+        let noise = self.options_as_ref().noise.clone();
+        let dynamic = self.options_as_ref().dynamic.clone();
+        if noise.is_none() && dynamic.is_none() {
+            evolve_with_memo(
+                self,
+                epoch,
+                best_or_rand,
+                difference_vector_count,
+                f_scale,
+                crossover_rate,
+            );
+            return;
+        }
+        // 再評価と環境の変化の検出を世代の間に挟む
+        for _ in 0..epoch {
+            if self.history_as_ref().aborted {
+                break;
             }
-            if individual.get_evaluations().len() == 0 {
-                let key = individual.get_features();
-                let key = &key.iter().join(",");
-                if self.memo_as_ref().contains_key(key) {
-                    individual.set_evaluations(self.memo_as_ref()[key].clone());
-                } else {
-                    let evaluation = individual.evaluate();
-                    self.memo_as_mut().insert(key.clone(), evaluation.clone());
-                    individual.set_evaluations(evaluation);
+            evolve_with_memo(
+                self,
+                1,
+                best_or_rand,
+                difference_vector_count,
+                f_scale,
+                crossover_rate,
+            );
+            let epochs = self.history_as_ref().epochs;
+            if let Some(noise) = &noise {
+                if epochs % noise.reevaluation_interval == 0 {
+                    self.reevaluate_population();
+                }
+            }
+            if let Some(dynamic) = &dynamic {
+                if epochs % dynamic.interval == 0 && self.detect_change().is_some() {
+                    self.respond_to_change();
                 }
             }
         }
-        self.set_individuals(tmp_individuals);
+    }
+}
 
-        for _ in 0..epoch {
-            let pre_individuals = self.get_individuals().clone();
-            let mut next_individuals = Vec::with_capacity(self.get_individuals().len());
-            for individual in pre_individuals {
-                let mutant = self.de_mutate(best_or_rand, difference_vector_count, f_scale);
-                let mut trial = individual.cross(
-                    &mutant,
-                    crossover_rate,
-                    &mut self.borrowed_random_generator(),
-                );
+/// evolve through the memo, or through the noise samples
+fn evolve_with_memo<I, G>(
+    group: &mut G,
+    epoch: usize,
+    best_or_rand: &str,
+    difference_vector_count: usize,
+    f_scale: f64,
+    crossover_rate: f64,
+) where
+    I: individual::ExtMinimum + Clone + Debug,
+    G: group::BaseDE<I>
+        + group::ExtMemoization<I>
+        + group::Configurable<I>
+        + group::RunHistory<I>
+        + group::SurrogateModeling<I>
+        + group::BatchEvaluation<I>
+        + group::Sampling<I>
+        + group::Fame<I>,
+{
+    if group.options_as_ref().noise.is_some() {
+        evolve(
+            group,
+            epoch,
+            best_or_rand,
+            difference_vector_count,
+            f_scale,
+            crossover_rate,
+            noise::evaluate_with_samples,
+            |group, candidates| group.screen_candidates(candidates),
+            |group, incumbent, trial| group.wins(incumbent, trial),
+        );
+    } else {
+        evolve(
+            group,
+            epoch,
+            best_or_rand,
+            difference_vector_count,
+            f_scale,
+            crossover_rate,
+            |group, individuals| {
+                robust::evaluate_robust(group, individuals, &mut evaluator::evaluate_with_memo)
+            },
+            |group, candidates| group.screen_candidates(candidates),
+            |_, incumbent, trial| !incumbent.is_better_than(trial),
+        );
+    }
+}
 
+/// ExtConfiguredDEとExtMemoizationDEの共通部分. `evaluate`はfeatures設定済みの個体を評価する.
+/// `screen`はsurrogateの候補から評価するtrialを選ぶ. `wins(group, incumbent, trial)`は選択の比較
+#[allow(clippy::too_many_arguments)]
+fn evolve<I, G, E, S, W>(
+    group: &mut G,
+    epoch: usize,
+    best_or_rand: &str,
+    difference_vector_count: usize,
+    f_scale: f64,
+    crossover_rate: f64,
+    mut evaluate: E,
+    mut screen: S,
+    wins: W,
+) where
+    I: individual::ExtMinimum + Clone,
+    G: group::BaseDE<I> + group::Configurable<I> + group::RunHistory<I> + group::Fame<I>,
+    E: FnMut(&mut G, &mut [I]),
+    S: FnMut(&mut G, &[I]) -> usize,
+    W: Fn(&G, &I, &I) -> bool,
+{
+    let mut tmp_individuals = group.get_individuals().clone();
+    let mut unevaluated = vec![];
+    for (i, individual) in tmp_individuals.iter_mut().enumerate() {
+        if individual.get_features().is_empty() {
+            individual.set_features(individual.identificate());
+            individual.set_evaluations(vec![]);
+        }
+        if individual.get_evaluations().is_empty() {
+            unevaluated.push(i);
+        }
+    }
+    let mut targets: Vec<I> = unevaluated
+        .iter()
+        .map(|i| tmp_individuals[*i].clone())
+        .collect();
+    let initial = group.history_as_ref().epochs == 0 && !unevaluated.is_empty();
+    evaluate(group, &mut targets);
+    for (i, target) in unevaluated.into_iter().zip(targets) {
+        tmp_individuals[i] = target;
+    }
+    group.set_individuals(tmp_individuals);
+    if initial && group.options_as_ref().initial_opposition && !group.history_as_ref().aborted {
+        let gene_len = group.get_gene_len();
+        jump_within(
+            group,
+            &mut evaluate,
+            &vec![0.0; gene_len],
+            &vec![1.0; gene_len],
+        );
+    }
+    group.update_hall_of_fame();
 
-                let key = trial.get_features();
-                let key = &key.iter().join(",");
-                if self.memo_as_ref().contains_key(key) {
-                    trial.set_evaluations(self.memo_as_ref()[key].clone());
-                } else {
-                    let evaluation = trial.evaluate();
-                    self.memo_as_mut().insert(key.clone(), evaluation.clone());
-                    trial.set_evaluations(evaluation);
+    for _ in 0..epoch {
+        // FailurePolicy::Abort
+        if group.history_as_ref().aborted {
+            break;
+        }
+        let options = group.options_as_ref().clone();
+        let species = match options.niching {
+            Niching::Speciation { radius } => Some(speciate(
+                group.get_individuals(),
+                radius,
+                options.niching_space,
+            )),
+            _ => None,
+        };
+        let mutate = |group: &mut G, i: usize| match &species {
+            Some(species) if species[i].len() > 2 * difference_vector_count => mutate_within(
+                group,
+                &species[i],
+                best_or_rand,
+                difference_vector_count,
+                f_scale,
+            ),
+            _ => group.de_mutate(best_or_rand, difference_vector_count, f_scale),
+        };
+        let candidates = options
+            .surrogate
+            .as_ref()
+            .map_or(1, |s| s.candidates.max(1));
+        let mut make_trial = |group: &mut G, i: usize, individual: &I| -> I {
+            let mut trials: Vec<I> = (0..candidates)
+                .map(|_| {
+                    let mutant = mutate(group, i);
+                    individual.cross(&mutant, crossover_rate, group.borrowed_random_generator())
+                })
+                .collect();
+            if trials.len() == 1 {
+                return trials.pop().unwrap();
+            }
+            let chosen = screen(group, &trials);
+            trials.swap_remove(chosen)
+        };
+        // FailurePolicy::Resampleでは評価に失敗したtrialの評価値が空になる
+        let max_resamples = match options.guard.as_ref().map(|guard| guard.policy) {
+            Some(evaluator::FailurePolicy::Resample { max_resamples }) => max_resamples,
+            _ => 0,
+        };
+
+        if options.updating == Updating::Immediate {
+            for i in 0..group.get_individuals().len() {
+                if group.history_as_ref().aborted {
+                    break;
+                }
+                let individual = group.get_individuals()[i].clone();
+                let mut trial = make_trial(group, i, &individual);
+                evaluate(group, std::slice::from_mut(&mut trial));
+                for _ in 0..max_resamples {
+                    if !trial.get_evaluations().is_empty() {
+                        break;
+                    }
+                    trial = make_trial(group, i, &individual);
+                    evaluate(group, std::slice::from_mut(&mut trial));
+                }
+                let target = select(group.get_individuals(), i, &trial, &options, |a, b| {
+                    wins(group, a, b)
+                });
+                if let Some(target) = target {
+                    group.set_individual(target, trial);
                 }
-                let winner = if individual.is_better_than(&trial) {
-                    individual
-                } else {
-                    trial
-                };
-                next_individuals.push(winner);
             }
-            self.set_individuals(next_individuals);
+            if group.history_as_ref().aborted {
+                break;
+            }
+            after_epoch(group, &mut evaluate);
+            continue;
+        }
+
+        // 世代の途中では集団を更新しないため、先に全てのtrialを作ってから評価する
+        let pre_individuals = group.get_individuals().clone();
+        let mut trials = Vec::with_capacity(pre_individuals.len());
+        for (i, individual) in pre_individuals.iter().enumerate() {
+            trials.push(make_trial(group, i, individual));
+        }
+        evaluate(group, &mut trials);
+        for _ in 0..max_resamples {
+            let failed: Vec<usize> = (0..trials.len())
+                .filter(|i| trials[*i].get_evaluations().is_empty())
+                .collect();
+            if failed.is_empty() {
+                break;
+            }
+            let mut resampled: Vec<I> = failed
+                .iter()
+                .map(|i| make_trial(group, *i, &pre_individuals[*i]))
+                .collect();
+            evaluate(group, &mut resampled);
+            for (i, trial) in failed.into_iter().zip(resampled) {
+                trials[i] = trial;
+            }
+        }
+
+        let mut next_individuals = pre_individuals;
+        for (i, trial) in trials.into_iter().enumerate() {
+            let target = select(&next_individuals, i, &trial, &options, |a, b| {
+                wins(group, a, b)
+            });
+            if let Some(target) = target {
+                next_individuals[target] = trial;
+            }
+        }
+        group.set_individuals(next_individuals);
+        if group.history_as_ref().aborted {
+            break;
+        }
+        after_epoch(group, &mut evaluate);
+    }
+}
+
+/// The index `trial` replaces, if it wins. `wins(incumbent, trial)` compares the evaluations.
+fn select<I, W>(
+    population: &[I],
+    parent: usize,
+    trial: &I,
+    options: &Options,
+    wins: W,
+) -> Option<usize>
+where
+    I: individual::ExtMinimum,
+    W: Fn(&I, &I) -> bool,
+{
+    let space = options.niching_space;
+    match options.niching {
+        Niching::None | Niching::Speciation { .. } => {
+            wins(&population[parent], trial).then_some(parent)
+        }
+        Niching::Crowding => {
+            let nearest = (0..population.len())
+                .min_by(|a, b| {
+                    let a = distance(&population[*a], trial, space);
+                    let b = distance(&population[*b], trial, space);
+                    a.partial_cmp(&b).unwrap()
+                })
+                .unwrap();
+            wins(&population[nearest], trial).then_some(nearest)
+        }
+        Niching::Sharing { radius, alpha } => {
+            let niche_count = |individual: &I| -> f64 {
+                population
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != parent)
+                    .map(|(_, other)| distance(individual, other, space))
+                    .filter(|d| *d < radius)
+                    .map(|d| 1.0 - (d / radius).powf(alpha))
+                    .sum::<f64>()
+                    + 1.0
+            };
+            // 負の評価値は割ると良くなってしまうため、正になるようずらしてから割る.
+            // 最小値を0にすると最も低いnicheが必ず負けるため、最悪の個体を最良の個体の半分にする
+            let (lowest, highest) = population
+                .iter()
+                .chain(std::iter::once(trial))
+                .filter_map(|individual| individual.get_evaluations().first())
+                // 評価に失敗した個体 (f64::MIN) は除く
+                .filter(|x| **x > f64::MIN)
+                .fold((f64::MAX, f64::MIN), |(lo, hi), x| (lo.min(*x), hi.max(*x)));
+            let floor = if lowest < 0.0 {
+                lowest - (highest - lowest)
+            } else {
+                0.0
+            };
+            let shared = |individual: &I| -> Vec<f64> {
+                let mut evaluations = individual.get_evaluations().clone();
+                if let Some(first) = evaluations.first_mut() {
+                    if *first > floor {
+                        *first = (*first - floor) / niche_count(individual);
+                    }
+                }
+                evaluations
+            };
+            let parent_shared = shared(&population[parent]);
+            let trial_shared = shared(trial);
+            (parent_shared.partial_cmp(&trial_shared).unwrap() == Ordering::Less).then_some(parent)
+        }
+    }
+}
+
+/// For each individual, the members of its species (best first).
+fn speciate<I>(individuals: &[I], radius: f64, space: Space) -> Vec<Vec<usize>>
+where
+    I: individual::Minimum,
+{
+    let mut order: Vec<usize> = (0..individuals.len()).collect();
+    order.sort_by(|a, b| {
+        individuals[*b]
+            .get_evaluations()
+            .partial_cmp(individuals[*a].get_evaluations())
+            .unwrap()
+    });
+    let mut species: Vec<Vec<usize>> = vec![];
+    let mut species_of = vec![0; individuals.len()];
+    for i in order {
+        let found = species.iter().position(|members| {
+            distance(&individuals[members[0]], &individuals[i], space) <= radius
+        });
+        match found {
+            Some(s) => {
+                species[s].push(i);
+                species_of[i] = s;
+            }
+            None => {
+                species_of[i] = species.len();
+                species.push(vec![i]);
+            }
+        }
+    }
+    species_of.into_iter().map(|s| species[s].clone()).collect()
+}
+
+/// de_mutate restricted to `members` (best first)
+fn mutate_within<I, G>(
+    group: &mut G,
+    members: &[usize],
+    best_or_rand: &str,
+    difference_vector_count: usize,
+    f_scale: f64,
+) -> I
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::BaseDE<I>,
+{
+    assert!(best_or_rand == "best" || best_or_rand == "rand");
+    let count = 1 + 2 * difference_vector_count;
+    let mut factor_indexes: Vec<usize> = rand::seq::index::sample(
+        group.borrowed_random_generator(),
+        members.len(),
+        count.min(members.len()),
+    )
+    .into_iter()
+    .map(|i| members[i])
+    .collect();
+    if best_or_rand == "best" {
+        let best = members[0];
+        if let Some(position) = factor_indexes.iter().position(|i| *i == best) {
+            factor_indexes.remove(position);
+        } else if factor_indexes.len() == count {
+            factor_indexes.pop();
+        }
+        factor_indexes.insert(0, best);
+    }
+
+    let individuals = group.get_individuals();
+    let genes = (0..group.get_gene_len())
+        .map(|i| {
+            let mut gene = individuals[factor_indexes[0]].get_genes()[i];
+            for j in 0..difference_vector_count {
+                let gene1 = individuals[factor_indexes[1 + 2 * j]].get_genes()[i];
+                let gene2 = individuals[factor_indexes[1 + 2 * j + 1]].get_genes()[i];
+                gene += f_scale * (gene1 - gene2);
+            }
+            gene.clamp(0.0, 1.0)
+        })
+        .collect();
+    I::from_genes(genes)
+}
+
+pub trait ExtNiching<I> {
+    /// The best individual of every niche: greedily, by evaluations, the individuals farther
+    /// than `radius` from all the already chosen ones.
+    fn get_distinct_optima(&self, radius: f64, space: Space) -> Vec<(usize, &I)>;
+}
+
+impl<I, G> ExtNiching<I> for G
+where
+    I: individual::ExtMinimum,
+    G: group::ExtMinimum<I>,
+{
+    fn get_distinct_optima(&self, radius: f64, space: Space) -> Vec<(usize, &I)> {
+        let individuals = self.get_individuals();
+        let mut order: Vec<usize> = (0..individuals.len()).collect();
+        order.sort_by(|a, b| {
+            individuals[*b]
+                .get_evaluations()
+                .partial_cmp(individuals[*a].get_evaluations())
+                .unwrap()
+        });
+        let mut optima: Vec<(usize, &I)> = vec![];
+        for i in order {
+            let individual = &individuals[i];
+            if optima
+                .iter()
+                .all(|(_, optimum)| distance(*optimum, individual, space) > radius)
+            {
+                optima.push((i, individual));
+            }
+        }
+        optima
+    }
+}
+
+fn after_epoch<I, G, E>(group: &mut G, evaluate: &mut E)
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::BaseDE<I> + group::Configurable<I> + group::RunHistory<I> + group::Fame<I>,
+    E: FnMut(&mut G, &mut [I]),
+{
+    if let Some(jumping_rate) = group.options_as_ref().jumping_rate {
+        if group.borrowed_random_generator().gen::<f64>() < jumping_rate {
+            jump(group, evaluate);
+        }
+    }
+    group.history_as_mut().epochs += 1;
+    if let Some(memetic) = group.options_as_ref().memetic.clone() {
+        if group.history_as_ref().epochs % memetic.interval == 0 {
+            local_search(group, &memetic, evaluate);
         }
     }
+    group.update_hall_of_fame();
+    if let Some(restart) = group.options_as_ref().restart.clone() {
+        restart_if_converged(group, &restart, evaluate);
+    }
+}
+
+fn local_search<I, G, E>(group: &mut G, memetic: &Memetic, evaluate: &mut E)
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::BaseDE<I> + group::RunHistory<I>,
+    E: FnMut(&mut G, &mut [I]),
+{
+    let individuals = group.get_individuals();
+    let mut order: Vec<usize> = (0..individuals.len()).collect();
+    order.sort_by(|a, b| {
+        individuals[*b]
+            .get_evaluations()
+            .partial_cmp(individuals[*a].get_evaluations())
+            .unwrap()
+    });
+    order.truncate(memetic.top);
+
+    let mut individuals = individuals.clone();
+    let mut spent = 0;
+    for i in order {
+        let mut evaluations = 0;
+        let improved = polish::search(memetic.search, &individuals[i], |individual: &mut I| {
+            if evaluations >= memetic.max_evaluations || group.history_as_ref().aborted {
+                return false;
+            }
+            evaluations += 1;
+            evaluate(group, std::slice::from_mut(individual));
+            true
+        });
+        spent += evaluations;
+        if improved.get_evaluations() > individuals[i].get_evaluations() {
+            match memetic.write_back {
+                WriteBack::Lamarckian => individuals[i] = improved,
+                WriteBack::Baldwinian => {
+                    individuals[i].set_evaluations(improved.get_evaluations().clone())
+                }
+            }
+        }
+    }
+    group.set_individuals(individuals);
+    group.history_as_mut().local_search_evaluations += spent;
+}
+
+/// (widest gene range, range of the first evaluation)
+pub fn spread<I: individual::Minimum>(individuals: &[I]) -> (f64, f64) {
+    let gene_len = individuals.first().map_or(0, |i| i.get_genes().len());
+    let mut gene_spread = 0.0f64;
+    for i in 0..gene_len {
+        let genes = individuals
+            .iter()
+            .map(|individual| individual.get_genes()[i]);
+        let (lo, hi) = genes.fold((f64::MAX, f64::MIN), |(lo, hi), g| (lo.min(g), hi.max(g)));
+        gene_spread = gene_spread.max(hi - lo);
+    }
+    let evaluations = individuals
+        .iter()
+        .filter_map(|i| i.get_evaluations().first());
+    let (lo, hi) = evaluations.fold((f64::MAX, f64::MIN), |(lo, hi), e| (lo.min(*e), hi.max(*e)));
+    (gene_spread, (hi - lo).max(0.0))
+}
+
+fn restart_if_converged<I, G, E>(group: &mut G, restart: &Restart, evaluate: &mut E)
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::BaseDE<I> + group::RunHistory<I>,
+    E: FnMut(&mut G, &mut [I]),
+{
+    let (gene_spread, evaluation_spread) = spread(group.get_individuals());
+    if gene_spread >= restart.gene_spread && evaluation_spread >= restart.evaluation_spread {
+        return;
+    }
+
+    let best = group.get_best().1.clone();
+    let len = group.get_individuals().len();
+    let population_len = ((len as f64 * restart.population_growth).ceil() as usize)
+        .min(restart.max_population)
+        .max(len);
+    let gene_len = group.get_gene_len();
+    let mut newcomers: Vec<I> = (1..population_len)
+        .map(|_| {
+            let mut individual = I::from_length(gene_len, group.borrowed_random_generator());
+            individual.set_features(individual.identificate());
+            individual
+        })
+        .collect();
+    evaluate(group, &mut newcomers);
+
+    let mut individuals = vec![best.clone()];
+    individuals.extend(newcomers);
+    group.set_individuals(individuals);
+
+    let epoch = group.history_as_ref().epochs;
+    group.history_as_mut().restarts.push(RestartRecord {
+        epoch,
+        gene_spread,
+        evaluation_spread,
+        population_len,
+        best_evaluations: best.get_evaluations().clone(),
+    });
+}
+
+fn jump<I, G, E>(group: &mut G, evaluate: &mut E)
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::BaseDE<I>,
+    E: FnMut(&mut G, &mut [I]),
+{
+    let gene_len = group.get_gene_len();
+    let mut lo = vec![1.0f64; gene_len];
+    let mut hi = vec![0.0f64; gene_len];
+    for individual in group.get_individuals().iter() {
+        for (i, gene) in individual.get_genes().iter().enumerate() {
+            lo[i] = lo[i].min(*gene);
+            hi[i] = hi[i].max(*gene);
+        }
+    }
+    jump_within(group, evaluate, &lo, &hi);
+}
+
+/// Evaluates the opposite points (lo + hi - gene) and keeps the best individuals of both.
+fn jump_within<I, G, E>(group: &mut G, evaluate: &mut E, lo: &[f64], hi: &[f64])
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::BaseDE<I>,
+    E: FnMut(&mut G, &mut [I]),
+{
+    let individuals = group.get_individuals().clone();
+    let mut opposites: Vec<I> = individuals
+        .iter()
+        .map(|individual| {
+            let genes = individual
+                .get_genes()
+                .iter()
+                .enumerate()
+                .map(|(i, gene)| (lo[i] + hi[i] - gene).clamp(0.0, 1.0))
+                .collect();
+            let mut opposite = I::from_genes(genes);
+            opposite.set_features(opposite.identificate());
+            opposite
+        })
+        .collect();
+    evaluate(group, &mut opposites);
+
+    // 安定ソートなので、同じ評価値なら元の個体が残る
+    let len = individuals.len();
+    let mut union = individuals;
+    union.extend(opposites);
+    union.sort_by(|a, b| {
+        b.get_evaluations()
+            .partial_cmp(a.get_evaluations())
+            .unwrap()
+    });
+    union.truncate(len);
+    group.set_individuals(union);
 }
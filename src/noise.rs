@@ -0,0 +1,337 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::evaluator::ExtBatchEvaluation;
+use crate::group::{self, BatchEvaluation, Configurable, RunHistory, Sampling};
+use crate::individual;
+use crate::memo::{ExtMemoLog, MemoKey};
+
+/// Noisy objectives: every memo key keeps all its samples, the memo holds their mean,
+/// and a trial replaces its parent only when it is significantly better.
+/// The samples are kept per memo key, so it needs `ExtMemoizationDE`; `ExtConfiguredDE`
+/// refuses it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Noise {
+    /// evaluations of a new memo key; at least 2 so the variance is known
+    pub samples: usize,
+    /// every `reevaluation_interval` epochs the population is sampled again
+    pub reevaluation_interval: usize,
+    /// samples added to every individual of the population then
+    pub reevaluations: usize,
+    /// z value of the test and of the confidence intervals (1.96 for 95 %)
+    pub z: f64,
+}
+
+/// Running mean and variance (Welford) of every evaluation. Saved with the group.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Samples {
+    pub count: usize,
+    pub mean: Vec<f64>,
+    m2: Vec<f64>,
+}
+
+impl Samples {
+    pub fn add(&mut self, evaluations: &[f64]) {
+        if self.count == 0 {
+            self.mean = vec![0.0; evaluations.len()];
+            self.m2 = vec![0.0; evaluations.len()];
+        }
+        self.count += 1;
+        let n = self.count as f64;
+        for (i, x) in evaluations.iter().enumerate() {
+            let delta = x - self.mean[i];
+            self.mean[i] += delta / n;
+            self.m2[i] += delta * (x - self.mean[i]);
+        }
+    }
+
+    /// unbiased sample variance; 0 with less than 2 samples
+    pub fn variance(&self) -> Vec<f64> {
+        if self.count < 2 {
+            return vec![0.0; self.mean.len()];
+        }
+        self.m2
+            .iter()
+            .map(|m2| m2 / (self.count - 1) as f64)
+            .collect()
+    }
+
+    /// variance of the mean
+    fn standard_error_squared(&self) -> f64 {
+        self.variance()
+            .first()
+            .map_or(0.0, |v| v / self.count as f64)
+    }
+
+    /// (lower, upper) of the mean, for every evaluation; unbounded with less than 2 samples
+    pub fn confidence_interval(&self, z: f64) -> Vec<(f64, f64)> {
+        if self.count < 2 {
+            return vec![(f64::NEG_INFINITY, f64::INFINITY); self.mean.len()];
+        }
+        self.mean
+            .iter()
+            .zip(self.variance())
+            .map(|(mean, variance)| {
+                let half = z * (variance / self.count as f64).sqrt();
+                (mean - half, mean + half)
+            })
+            .collect()
+    }
+}
+
+pub type SampleMemo<F> = HashMap<MemoKey<F>, Samples>;
+
+/// serde of `SampleMemo` with the written memo keys; hashed keys get a leading '#'.
+/// Keys are converted to the group's keying when they are used.
+pub(crate) mod sample_keys {
+    use std::collections::{BTreeMap, HashMap};
+    use std::fmt::Display;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{SampleMemo, Samples};
+    use crate::memo::{parse_written_key, MemoKey, MemoKeying};
+
+    pub fn serialize<F, S>(samples: &SampleMemo<F>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        F: Display,
+        S: Serializer,
+    {
+        let written: BTreeMap<String, &Samples> = samples
+            .iter()
+            .map(|(key, samples)| match key {
+                MemoKey::Hash128(_) => (format!("#{}", key), samples),
+                _ => (key.to_string(), samples),
+            })
+            .collect();
+        written.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, F, D>(deserializer: D) -> Result<SampleMemo<F>, D::Error>
+    where
+        F: PartialEq,
+        D: Deserializer<'de>,
+    {
+        let written = HashMap::<String, Samples>::deserialize(deserializer)?;
+        Ok(written
+            .into_iter()
+            .map(|(key, samples)| match key.strip_prefix('#') {
+                Some(hash) => (
+                    parse_written_key(hash.to_string(), MemoKeying::Hash128),
+                    samples,
+                ),
+                None => (MemoKey::Joined(key), samples),
+            })
+            .collect())
+    }
+}
+
+/// Samples loaded with the group keep their written keys until the first use.
+fn align_sample_keys<I, G>(group: &mut G)
+where
+    I: individual::ExtMinimum,
+    G: group::ExtMemoization<I> + Sampling<I>,
+{
+    let keying = *group.memo_keying_as_ref();
+    if group
+        .samples_as_ref()
+        .keys()
+        .all(|key| key.keying() == keying)
+    {
+        return;
+    }
+    let samples = std::mem::take(group.samples_as_mut());
+    for (key, samples) in samples {
+        // hashされたkeyは他のkeyingでは使えない
+        if let Some(key) = group.to_memo_key(key) {
+            group.samples_as_mut().insert(key, samples);
+        }
+    }
+}
+
+/// The best individual with the confidence interval of its mean.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub count: usize,
+    pub mean: Vec<f64>,
+    pub interval: Vec<(f64, f64)>,
+}
+
+pub trait ExtNoise<I> {
+    /// get_best with the samples of its memo key. Without `Options::noise` the interval is the
+    /// evaluation itself; with it, a best without samples has an unbounded interval.
+    fn get_best_with_confidence(&self) -> (usize, &I, Estimate);
+    /// Adds `Noise::reevaluations` samples to every individual of the population and updates
+    /// the memo and their evaluations with the new means.
+    fn reevaluate_population(&mut self);
+    /// Whether `trial` should replace `incumbent`: its mean is higher by more than `z`
+    /// standard errors of the difference (Welch). Without noise, the usual comparison.
+    fn wins(&self, incumbent: &I, trial: &I) -> bool;
+}
+
+impl<I, G> ExtNoise<I> for G
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::BaseDE<I>
+        + group::ExtMemoization<I>
+        + BatchEvaluation<I>
+        + Configurable<I>
+        + RunHistory<I>
+        + Sampling<I>,
+{
+    fn get_best_with_confidence(&self) -> (usize, &I, Estimate) {
+        let (index, best) = self.get_best();
+        let Some(noise) = self.options_as_ref().noise.as_ref() else {
+            let estimate = Estimate {
+                count: 1,
+                mean: best.get_evaluations().clone(),
+                interval: best.get_evaluations().iter().map(|e| (*e, *e)).collect(),
+            };
+            return (index, best, estimate);
+        };
+        let key = self.memo_key(best.get_features());
+        let samples = self.samples_as_ref().get(&key).cloned().unwrap_or_else(|| {
+            let mut samples = Samples::default();
+            samples.add(best.get_evaluations());
+            samples
+        });
+        let estimate = Estimate {
+            count: samples.count,
+            interval: samples.confidence_interval(noise.z),
+            mean: samples.mean,
+        };
+        (index, best, estimate)
+    }
+
+    fn reevaluate_population(&mut self) {
+        let Some(noise) = self.options_as_ref().noise.clone() else {
+            return;
+        };
+        align_sample_keys(self);
+        // 同じkeyの個体は1回だけ評価する
+        let mut seen = HashSet::new();
+        let mut batch: Vec<I> = vec![];
+        for individual in self.get_individuals().iter() {
+            if seen.insert(self.memo_key(individual.get_features())) {
+                batch.extend(std::iter::repeat(individual.clone()).take(noise.reevaluations));
+            }
+        }
+        let failed: HashSet<usize> = self.evaluate_individuals(&mut batch).into_iter().collect();
+        for (k, individual) in batch.iter().enumerate() {
+            if failed.contains(&k) {
+                continue;
+            }
+            let key = self.memo_key(individual.get_features());
+            self.samples_as_mut()
+                .entry(key)
+                .or_default()
+                .add(individual.get_evaluations());
+        }
+
+        let mut individuals = self.get_individuals().clone();
+        for individual in individuals.iter_mut() {
+            let key = self.memo_key(individual.get_features());
+            if let Some(samples) = self.samples_as_ref().get(&key) {
+                let mean = samples.mean.clone();
+                individual.set_evaluations(mean.clone());
+                self.store_memo(key, mean);
+            }
+        }
+        self.set_individuals(individuals);
+        // 平均が変わる度に追記するとlogが伸び続けるので、1 keyに1行に書き直す
+        self.compact_memo_log();
+        self.check_failures();
+    }
+
+    fn wins(&self, incumbent: &I, trial: &I) -> bool {
+        let Some(noise) = self.options_as_ref().noise.as_ref() else {
+            return !incumbent.is_better_than(trial);
+        };
+        let (Some(a), Some(b)) = (
+            incumbent.get_evaluations().first(),
+            trial.get_evaluations().first(),
+        ) else {
+            return !incumbent.is_better_than(trial);
+        };
+        let error = |individual: &I| {
+            let key = self.memo_key(individual.get_features());
+            self.samples_as_ref()
+                .get(&key)
+                .map_or(0.0, Samples::standard_error_squared)
+        };
+        let standard_error = (error(incumbent) + error(trial)).sqrt();
+        if standard_error == 0.0 {
+            return !incumbent.is_better_than(trial);
+        }
+        b - a > noise.z * standard_error
+    }
+}
+
+/// evaluate_with_memo with `Noise::samples` evaluations of every new key
+pub fn evaluate_with_samples<I, G>(group: &mut G, individuals: &mut [I])
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::ExtMemoization<I>
+        + BatchEvaluation<I>
+        + Configurable<I>
+        + RunHistory<I>
+        + Sampling<I>,
+{
+    let samples = match &group.options_as_ref().noise {
+        Some(noise) => {
+            assert!(noise.samples >= 2, "Noise::samples must be at least 2");
+            noise.samples
+        }
+        None => 1,
+    };
+    align_sample_keys(group);
+    let keys: Vec<MemoKey<I::Feature>> = individuals
+        .iter()
+        .map(|individual| group.memo_key(individual.get_features()))
+        .collect();
+    let mut pending: HashMap<&MemoKey<I::Feature>, usize> = HashMap::new();
+    let mut misses: Vec<usize> = vec![];
+    let mut duplicates: Vec<(usize, usize)> = vec![];
+    for (i, individual) in individuals.iter_mut().enumerate() {
+        if let Some(first) = pending.get(&keys[i]) {
+            duplicates.push((i, *first));
+        } else if let Some(evaluation) = group.lookup_memo(&keys[i]) {
+            individual.set_evaluations(evaluation);
+        } else {
+            pending.insert(&keys[i], i);
+            misses.push(i);
+        }
+    }
+
+    let mut batch: Vec<I> = misses
+        .iter()
+        .flat_map(|i| std::iter::repeat(individuals[*i].clone()).take(samples))
+        .collect();
+    let failed: HashSet<usize> = group.evaluate_individuals(&mut batch).into_iter().collect();
+    for (j, i) in misses.into_iter().enumerate() {
+        for (k, sample) in batch.iter().enumerate().skip(j * samples).take(samples) {
+            if !failed.contains(&k) {
+                group
+                    .samples_as_mut()
+                    .entry(keys[i].clone())
+                    .or_default()
+                    .add(sample.get_evaluations());
+            }
+        }
+        match group.samples_as_ref().get(&keys[i]) {
+            Some(sampled) => {
+                let mean = sampled.mean.clone();
+                individuals[i].set_evaluations(mean.clone());
+                group.insert_memo(keys[i].clone(), mean);
+            }
+            // 全て失敗した. 失敗時の評価値を使う
+            None => individuals[i].set_evaluations(batch[j * samples].get_evaluations().clone()),
+        }
+    }
+    for (i, first) in duplicates {
+        let evaluation = individuals[first].get_evaluations().clone();
+        individuals[i].set_evaluations(evaluation);
+    }
+    group.check_failures();
+}
@@ -0,0 +1,401 @@
+use itertools::Itertools;
+
+use crate::evaluator::{self, ExtBatchEvaluation};
+use crate::group;
+use crate::individual;
+use crate::robust;
+
+/// Local search started from the best individual, like scipy's `polish=True`.
+/// All methods maximize the first evaluation and stay in the gene bounds [0.0, 1.0].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Polish {
+    /// coordinate search with a halving step
+    Pattern { step: f64 },
+    /// derivative free; points outside the bounds are projected onto them
+    NelderMead {
+        /// edge length of the initial simplex
+        step: f64,
+    },
+    /// projected L-BFGS with forward differences (L-BFGS-B style)
+    Lbfgsb {
+        /// finite difference step
+        step: f64,
+        /// number of correction pairs kept
+        memory: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolishReport {
+    /// evaluations spent, memo hits excluded
+    pub evaluations: usize,
+    pub before: Vec<f64>,
+    pub after: Vec<f64>,
+    /// whether the best individual was replaced
+    pub improved: bool,
+}
+
+pub trait ExtPolish<I> {
+    /// Refine the best individual with at most `max_evaluations` evaluations.
+    /// Evaluations go through the memo, so known points are free.
+    /// With `Options::robustness` the perturbed copies are evaluated and aggregated, and
+    /// each new copy counts as one evaluation.
+    fn polish(&mut self, method: Polish, max_evaluations: usize) -> PolishReport;
+}
+
+impl<I, G> ExtPolish<I> for G
+where
+    I: individual::ExtMinimum + Clone,
+    G: group::ExtMemoization<I>
+        + group::BatchEvaluation<I>
+        + group::Configurable<I>
+        + group::RunHistory<I>,
+{
+    fn polish(&mut self, method: Polish, max_evaluations: usize) -> PolishReport {
+        let (best_index, best) = self.get_best();
+        let best = best.clone();
+        let robustness = self.options_as_ref().robustness.clone();
+        let mut evaluations = 0;
+        let polished = search(method, &best, |individual: &mut I| {
+            if self.history_as_ref().aborted {
+                return false;
+            }
+            if let Some(robustness) = &robustness {
+                // perturbed copies not in the memo
+                let new = robustness
+                    .perturb(individual.get_genes())
+                    .into_iter()
+                    .map(|genes| {
+                        let copy = I::from_genes(genes);
+                        self.memo_key(&copy.identificate())
+                    })
+                    .filter(|key| !self.memo_as_ref().contains_key(key))
+                    .unique()
+                    .count();
+                if evaluations + new > max_evaluations {
+                    return false;
+                }
+                evaluations += new;
+                robust::evaluate_robust(
+                    self,
+                    std::slice::from_mut(individual),
+                    &mut evaluator::evaluate_with_memo,
+                );
+                return true;
+            }
+            let key = self.memo_key(individual.get_features());
+            if evaluations >= max_evaluations && !self.memo_as_ref().contains_key(&key) {
+                return false;
+            }
+            let evaluation = match self.lookup_memo(&key) {
+                Some(evaluation) => evaluation,
+                None => {
+                    evaluations += 1;
+                    let failed = self.evaluate_individuals(std::slice::from_mut(individual));
+                    let evaluation = individual.get_evaluations().clone();
+                    if failed.is_empty() {
+                        self.insert_memo(key, evaluation.clone());
+                    }
+                    self.check_failures();
+                    evaluation
+                }
+            };
+            individual.set_evaluations(evaluation);
+            true
+        });
+
+        let improved = polished.get_evaluations() > best.get_evaluations();
+        if improved {
+            self.set_individual(best_index, polished.clone());
+        }
+        PolishReport {
+            evaluations,
+            before: best.get_evaluations().clone(),
+            after: polished.get_evaluations().clone(),
+            improved,
+        }
+    }
+}
+
+/// Local search from `start` (evaluated); returns the best individual seen.
+/// `evaluate` sets the evaluations of an individual with features, or returns false when
+/// the budget is spent.
+pub fn search<I, F>(method: Polish, start: &I, evaluate: F) -> I
+where
+    I: individual::ExtMinimum + Clone,
+    F: FnMut(&mut I) -> bool,
+{
+    let mut objective = Objective {
+        evaluate,
+        best: start.clone(),
+    };
+    let genes = start.get_genes().clone();
+    match method {
+        Polish::NelderMead { step } => nelder_mead(&mut objective, genes, step),
+        Polish::Lbfgsb { step, memory } => lbfgsb(&mut objective, genes, step, memory),
+        Polish::Pattern { step } => pattern(&mut objective, genes, step),
+    }
+    objective.best
+}
+
+/// The first evaluation of genes, within the budget.
+struct Objective<I, F> {
+    evaluate: F,
+    best: I,
+}
+
+impl<I, F> Objective<I, F>
+where
+    I: individual::ExtMinimum + Clone,
+    F: FnMut(&mut I) -> bool,
+{
+    /// `None` when the budget is spent
+    fn value(&mut self, genes: &[f64]) -> Option<f64> {
+        let mut individual = I::from_genes(genes.iter().map(|g| g.clamp(0.0, 1.0)).collect());
+        individual.set_features(individual.identificate());
+        if !(self.evaluate)(&mut individual) {
+            return None;
+        }
+        let value = individual
+            .get_evaluations()
+            .first()
+            .copied()
+            .unwrap_or(f64::MIN);
+        if individual.get_evaluations() > self.best.get_evaluations() {
+            self.best = individual;
+        }
+        Some(value)
+    }
+}
+
+/// compass search: try ±step on every gene, halve the step when nothing improves
+fn pattern<I, F>(objective: &mut Objective<I, F>, start: Vec<f64>, step: f64)
+where
+    I: individual::ExtMinimum + Clone,
+    F: FnMut(&mut I) -> bool,
+{
+    let mut x = start;
+    let Some(mut fx) = objective.value(&x) else {
+        return;
+    };
+    let mut step = step;
+    while step > 1e-9 {
+        let mut improved = false;
+        for i in 0..x.len() {
+            for direction in [1.0, -1.0] {
+                let mut candidate = x.clone();
+                candidate[i] = (candidate[i] + direction * step).clamp(0.0, 1.0);
+                if candidate[i] == x[i] {
+                    continue;
+                }
+                let Some(value) = objective.value(&candidate) else {
+                    return;
+                };
+                if value > fx {
+                    x = candidate;
+                    fx = value;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        if !improved {
+            step /= 2.0;
+        }
+    }
+}
+
+fn nelder_mead<I, F>(objective: &mut Objective<I, F>, start: Vec<f64>, step: f64)
+where
+    I: individual::ExtMinimum + Clone,
+    F: FnMut(&mut I) -> bool,
+{
+    let project = |x: Vec<f64>| -> Vec<f64> { x.into_iter().map(|g| g.clamp(0.0, 1.0)).collect() };
+    let combine = |a: &[f64], b: &[f64], t: f64| -> Vec<f64> {
+        project(a.iter().zip(b).map(|(a, b)| a + t * (b - a)).collect())
+    };
+    let n = start.len();
+    // 最小化として扱う
+    let mut simplex: Vec<(Vec<f64>, f64)> = Vec::with_capacity(n + 1);
+    let Some(value) = objective.value(&start) else {
+        return;
+    };
+    simplex.push((start.clone(), -value));
+    for i in 0..n {
+        let mut vertex = start.clone();
+        vertex[i] += if vertex[i] + step <= 1.0 { step } else { -step };
+        let Some(value) = objective.value(&vertex) else {
+            return;
+        };
+        simplex.push((vertex, -value));
+    }
+
+    loop {
+        simplex.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        let (best, worst) = (simplex[0].1, simplex[n].1);
+        if (worst - best).abs() <= 1e-12 * (1.0 + best.abs()) {
+            return;
+        }
+        let mut centroid = vec![0.0; n];
+        for (vertex, _) in simplex[..n].iter() {
+            for (c, x) in centroid.iter_mut().zip(vertex) {
+                *c += x / n as f64;
+            }
+        }
+
+        let reflected = combine(&centroid, &simplex[n].0, -1.0);
+        let Some(reflected_value) = objective.value(&reflected).map(|v| -v) else {
+            return;
+        };
+        if reflected_value < simplex[0].1 {
+            let expanded = combine(&centroid, &simplex[n].0, -2.0);
+            let Some(expanded_value) = objective.value(&expanded).map(|v| -v) else {
+                return;
+            };
+            simplex[n] = if expanded_value < reflected_value {
+                (expanded, expanded_value)
+            } else {
+                (reflected, reflected_value)
+            };
+        } else if reflected_value < simplex[n - 1].1 {
+            simplex[n] = (reflected, reflected_value);
+        } else {
+            let (towards, towards_value) = if reflected_value < simplex[n].1 {
+                (reflected, reflected_value)
+            } else {
+                simplex[n].clone()
+            };
+            let contracted = combine(&centroid, &towards, 0.5);
+            let Some(contracted_value) = objective.value(&contracted).map(|v| -v) else {
+                return;
+            };
+            if contracted_value < towards_value {
+                simplex[n] = (contracted, contracted_value);
+            } else {
+                let best = simplex[0].0.clone();
+                for vertex in simplex[1..].iter_mut() {
+                    let shrunk = combine(&best, &vertex.0, 0.5);
+                    let Some(value) = objective.value(&shrunk) else {
+                        return;
+                    };
+                    *vertex = (shrunk, -value);
+                }
+            }
+        }
+    }
+}
+
+fn lbfgsb<I, F>(objective: &mut Objective<I, F>, start: Vec<f64>, step: f64, memory: usize)
+where
+    I: individual::ExtMinimum + Clone,
+    F: FnMut(&mut I) -> bool,
+{
+    let project = |x: Vec<f64>| -> Vec<f64> { x.into_iter().map(|g| g.clamp(0.0, 1.0)).collect() };
+    let dot = |a: &[f64], b: &[f64]| -> f64 { a.iter().zip(b).map(|(a, b)| a * b).sum() };
+
+    // 最小化として扱う. 上限にある遺伝子は後退差分にする
+    let gradient = |objective: &mut Objective<I, F>, x: &[f64], fx: f64| -> Option<Vec<f64>> {
+        let mut gradient = Vec::with_capacity(x.len());
+        for i in 0..x.len() {
+            let h = if x[i] + step <= 1.0 { step } else { -step };
+            let mut shifted = x.to_vec();
+            shifted[i] += h;
+            gradient.push((-objective.value(&shifted)? - fx) / h);
+        }
+        Some(gradient)
+    };
+
+    let mut x = start;
+    let Some(value) = objective.value(&x) else {
+        return;
+    };
+    let mut fx = -value;
+    let Some(mut g) = gradient(objective, &x, fx) else {
+        return;
+    };
+    let mut corrections: Vec<(Vec<f64>, Vec<f64>)> = vec![];
+
+    loop {
+        // 境界で外向きの成分を除いた勾配
+        let free: Vec<bool> = x
+            .iter()
+            .zip(g.iter())
+            .map(|(x, g)| !((*x <= 0.0 && *g > 0.0) || (*x >= 1.0 && *g < 0.0)))
+            .collect();
+        let projected: Vec<f64> = g
+            .iter()
+            .zip(free.iter())
+            .map(|(g, free)| if *free { *g } else { 0.0 })
+            .collect();
+        if dot(&projected, &projected).sqrt() <= 1e-10 {
+            return;
+        }
+
+        // two-loop recursion
+        let mut q = projected.clone();
+        let mut alphas = Vec::with_capacity(corrections.len());
+        for (s, y) in corrections.iter().rev() {
+            let alpha = dot(s, &q) / dot(y, s);
+            for (q, y) in q.iter_mut().zip(y) {
+                *q -= alpha * y;
+            }
+            alphas.push(alpha);
+        }
+        let scale = corrections
+            .last()
+            .map_or(1.0, |(s, y)| dot(s, y) / dot(y, y));
+        let mut direction: Vec<f64> = q.iter().map(|q| scale * q).collect();
+        for ((s, y), alpha) in corrections.iter().zip(alphas.into_iter().rev()) {
+            let beta = dot(y, &direction) / dot(y, s);
+            for (d, s) in direction.iter_mut().zip(s) {
+                *d += (alpha - beta) * s;
+            }
+        }
+        for (d, free) in direction.iter_mut().zip(free.iter()) {
+            *d = if *free { -*d } else { 0.0 };
+        }
+        // 準ニュートン方向が下りでなければ最急降下にする
+        if dot(&direction, &projected) >= 0.0 {
+            direction = projected.iter().map(|g| -g).collect();
+            corrections.clear();
+        }
+
+        // projected backtracking line search (Armijo)
+        let mut t = if corrections.is_empty() {
+            1.0 / dot(&projected, &projected).sqrt()
+        } else {
+            1.0
+        };
+        let mut accepted = None;
+        while t > 1e-12 {
+            let candidate = project(x.iter().zip(&direction).map(|(x, d)| x + t * d).collect());
+            let Some(value) = objective.value(&candidate) else {
+                return;
+            };
+            let moved: Vec<f64> = candidate.iter().zip(&x).map(|(c, x)| c - x).collect();
+            if -value <= fx + 1e-4 * dot(&projected, &moved) && dot(&moved, &moved) > 0.0 {
+                accepted = Some((candidate, -value));
+                break;
+            }
+            t /= 2.0;
+        }
+        let Some((next, next_fx)) = accepted else {
+            return;
+        };
+        let Some(next_g) = gradient(objective, &next, next_fx) else {
+            return;
+        };
+
+        let s: Vec<f64> = next.iter().zip(&x).map(|(a, b)| a - b).collect();
+        let y: Vec<f64> = next_g.iter().zip(&g).map(|(a, b)| a - b).collect();
+        if dot(&s, &y) > 1e-12 {
+            corrections.push((s, y));
+            if corrections.len() > memory {
+                corrections.remove(0);
+            }
+        }
+        x = next;
+        fx = next_fx;
+        g = next_g;
+    }
+}
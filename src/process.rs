@@ -0,0 +1,303 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::evaluator::BatchEvaluator;
+use crate::individual;
+
+/// One request line: `{"id": 0, "genes": [0.5, ...], "features": [3, "a", ...]}`.
+/// Features that parse as numbers are sent as numbers, the others as strings.
+pub fn format_request<I: individual::Minimum>(id: usize, individual: &I) -> String {
+    let features: Vec<Value> = individual
+        .get_features()
+        .iter()
+        .map(|feature| {
+            let text = feature.to_string();
+            match text.parse::<f64>() {
+                Ok(number) if number.is_finite() => json!(number),
+                _ => json!(text),
+            }
+        })
+        .collect();
+    json!({"id": id, "genes": individual.get_genes(), "features": features}).to_string()
+}
+
+/// One response line: `{"id": 0, "evaluations": [1.0, ...]}`. JSON has no NaN or infinities,
+/// so those are written as the strings "NaN", "inf" and "-inf".
+pub fn format_response(id: usize, evaluations: &[f64]) -> String {
+    let evaluations: Vec<Value> = evaluations
+        .iter()
+        .map(|evaluation| {
+            if evaluation.is_finite() {
+                json!(evaluation)
+            } else {
+                json!(evaluation.to_string())
+            }
+        })
+        .collect();
+    json!({"id": id, "evaluations": evaluations}).to_string()
+}
+
+/// Reads the lines of `format_response`; `null`, which JSON serializers usually write for NaN
+/// and infinities, is read as NaN. Other lines (logs of the simulator) give `None`.
+pub fn parse_response(line: &str) -> Option<(usize, Vec<f64>)> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let id = value.get("id")?.as_u64()? as usize;
+    let evaluations = value
+        .get("evaluations")?
+        .as_array()?
+        .iter()
+        .map(|v| match v {
+            Value::Null => Some(f64::NAN),
+            Value::String(text) => text.parse().ok(),
+            _ => v.as_f64(),
+        })
+        .collect::<Option<Vec<f64>>>()?;
+    Some((id, evaluations))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub evaluations: usize,
+    pub timeouts: usize,
+    pub crashes: usize,
+    /// workers started, including the first ones
+    pub spawns: usize,
+}
+
+/// (worker, incarnation, line); `None` at the end of stdout
+type Message = (usize, usize, Option<String>);
+
+#[derive(Debug)]
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    incarnation: usize,
+    /// (job, started)
+    job: Option<(usize, Instant)>,
+}
+
+/// Long-lived worker processes speaking JSON lines: one request per line on stdin, one
+/// response per line on stdout. Each worker evaluates one individual at a time. Workers that
+/// exit or exceed `timeout` are restarted and their individual is sent again, at most
+/// `max_attempts` times in total.
+#[derive(Debug)]
+pub struct ProcessPool {
+    pub program: String,
+    pub args: Vec<String>,
+    pub envs: Vec<(String, String)>,
+    pub worker_count: usize,
+    pub timeout: Duration,
+    pub max_attempts: usize,
+    workers: Vec<Option<Worker>>,
+    incarnations: usize,
+    sender: Sender<Message>,
+    receiver: Receiver<Message>,
+    stats: PoolStats,
+}
+
+impl ProcessPool {
+    /// 60 s timeout, 3 attempts. Workers start at the first evaluation.
+    pub fn new(program: &str, args: &[&str], worker_count: usize) -> Self {
+        assert!(worker_count > 0);
+        let (sender, receiver) = channel();
+        Self {
+            program: program.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            envs: vec![],
+            worker_count,
+            timeout: Duration::from_secs(60),
+            max_attempts: 3,
+            workers: vec![],
+            incarnations: 0,
+            sender,
+            receiver,
+            stats: PoolStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    fn spawn(&mut self, index: usize) -> Worker {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .envs(self.envs.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|e| panic!("cannot start {}: {}", self.program, e));
+        self.incarnations += 1;
+        self.stats.spawns += 1;
+        let incarnation = self.incarnations;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send((index, incarnation, Some(line))).is_err() {
+                    return;
+                }
+            }
+            let _ = sender.send((index, incarnation, None));
+        });
+        Worker {
+            child,
+            stdin,
+            incarnation,
+            job: None,
+        }
+    }
+
+    fn kill(&mut self, index: usize) {
+        if let Some(mut worker) = self.workers[index].take() {
+            let _ = worker.child.kill();
+            let _ = worker.child.wait();
+        }
+    }
+
+    /// Sends `job` to the worker, restarting it first if needed. false if the write failed.
+    fn send(&mut self, index: usize, job: usize, request: &str) -> bool {
+        if self.workers[index].is_none() {
+            self.workers[index] = Some(self.spawn(index));
+        }
+        let worker = self.workers[index].as_mut().unwrap();
+        worker.job = Some((job, Instant::now()));
+        writeln!(worker.stdin, "{}", request).is_ok() && worker.stdin.flush().is_ok()
+    }
+
+    fn evaluate_requests(&mut self, requests: &[String]) -> Vec<Vec<f64>> {
+        self.workers.resize_with(self.worker_count, || None);
+        let mut results: Vec<Option<Vec<f64>>> = vec![None; requests.len()];
+        let mut attempts = vec![0; requests.len()];
+        let mut queue: VecDeque<usize> = (0..requests.len()).collect();
+        let mut remaining = requests.len();
+
+        while remaining > 0 {
+            for index in 0..self.worker_count {
+                let idle = self.workers[index]
+                    .as_ref()
+                    .map_or(true, |worker| worker.job.is_none());
+                if !idle {
+                    continue;
+                }
+                let Some(job) = queue.pop_front() else {
+                    break;
+                };
+                attempts[job] += 1;
+                if !self.send(index, job, &requests[job]) {
+                    self.fail(index, job, &mut attempts, &mut queue);
+                }
+            }
+
+            let deadline = self
+                .workers
+                .iter()
+                .flatten()
+                .filter_map(|worker| worker.job.map(|(_, started)| started + self.timeout))
+                .min();
+            // 書き込みに失敗しただけなら待たずに送り直す
+            let Some(deadline) = deadline else {
+                continue;
+            };
+            let wait = deadline.saturating_duration_since(Instant::now());
+            match self.receiver.recv_timeout(wait) {
+                Ok((index, incarnation, line)) => {
+                    let current = self.workers[index]
+                        .as_ref()
+                        .is_some_and(|worker| worker.incarnation == incarnation);
+                    if !current {
+                        continue;
+                    }
+                    let job = self.workers[index].as_ref().unwrap().job;
+                    match line {
+                        Some(line) => {
+                            if let (Some((id, evaluations)), Some((job, _))) =
+                                (parse_response(&line), job)
+                            {
+                                if id == job {
+                                    results[job] = Some(evaluations);
+                                    remaining -= 1;
+                                    self.stats.evaluations += 1;
+                                    self.workers[index].as_mut().unwrap().job = None;
+                                }
+                            }
+                        }
+                        None => {
+                            self.stats.crashes += 1;
+                            self.kill(index);
+                            if let Some((job, _)) = job {
+                                self.fail(index, job, &mut attempts, &mut queue);
+                            }
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    let now = Instant::now();
+                    for index in 0..self.worker_count {
+                        let expired = self.workers[index].as_ref().and_then(|worker| {
+                            worker
+                                .job
+                                .filter(|(_, started)| now >= *started + self.timeout)
+                        });
+                        if let Some((job, _)) = expired {
+                            self.stats.timeouts += 1;
+                            self.kill(index);
+                            self.fail(index, job, &mut attempts, &mut queue);
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => unreachable!(),
+            }
+        }
+        results.into_iter().map(Option::unwrap).collect()
+    }
+
+    fn fail(
+        &mut self,
+        index: usize,
+        job: usize,
+        attempts: &mut [usize],
+        queue: &mut VecDeque<usize>,
+    ) {
+        self.kill(index);
+        if attempts[job] >= self.max_attempts {
+            panic!(
+                "{} failed to evaluate request {} after {} attempts",
+                self.program, job, attempts[job]
+            );
+        }
+        // 先頭に戻して、他の個体より先に再評価する
+        queue.push_front(job);
+    }
+}
+
+impl Drop for ProcessPool {
+    fn drop(&mut self) {
+        for index in 0..self.workers.len() {
+            self.kill(index);
+        }
+    }
+}
+
+impl<I> BatchEvaluator<I> for ProcessPool
+where
+    I: individual::Minimum,
+{
+    fn evaluate_batch(&mut self, individuals: &[I]) -> Vec<Vec<f64>> {
+        let requests: Vec<String> = individuals
+            .iter()
+            .enumerate()
+            .map(|(id, individual)| format_request(id, individual))
+            .collect();
+        self.evaluate_requests(&requests)
+    }
+}
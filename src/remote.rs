@@ -0,0 +1,323 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::evaluator::BatchEvaluator;
+use crate::individual;
+use crate::process::{format_request, format_response, parse_response};
+
+/// Coordinator and workers exchange the JSON lines of `process` over TCP: the coordinator
+/// sends `{"id", "genes", "features"}` and a worker answers `{"id", "evaluations"}`.
+#[derive(Debug)]
+enum Event {
+    Connected(usize, TcpStream),
+    Line(usize, String),
+    Disconnected(usize),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CoordinatorStats {
+    pub evaluations: usize,
+    pub connections: usize,
+    pub disconnects: usize,
+    /// requests sent again because their worker disconnected or timed out
+    pub requeued: usize,
+    /// workers disconnected because a request took longer than `Coordinator::timeout`
+    pub timeouts: usize,
+}
+
+#[derive(Debug)]
+struct Connection {
+    stream: TcpStream,
+    /// (job, started)
+    job: Option<(usize, Instant)>,
+}
+
+/// Sends the trials to the connected workers, one request per worker at a time. The work
+/// of a disconnected worker, or of a worker that exceeds `timeout`, goes back to the queue.
+/// Workers may connect at any time.
+#[derive(Debug)]
+pub struct Coordinator {
+    /// how long to wait when no worker is connected
+    pub connect_timeout: Duration,
+    /// how long a request may take; the worker is disconnected then
+    pub timeout: Duration,
+    local_addr: SocketAddr,
+    receiver: Receiver<Event>,
+    connections: HashMap<usize, Connection>,
+    stopped: Arc<AtomicBool>,
+    stats: CoordinatorStats,
+}
+
+impl Coordinator {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let (sender, receiver) = channel();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stop = stopped.clone();
+        std::thread::spawn(move || {
+            for (id, stream) in listener.incoming().enumerate() {
+                if stop.load(Ordering::SeqCst) {
+                    return;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let _ = stream.set_nodelay(true);
+                let Ok(reader) = stream.try_clone() else {
+                    continue;
+                };
+                if sender.send(Event::Connected(id, stream)).is_err() {
+                    return;
+                }
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    for line in BufReader::new(reader).lines() {
+                        let Ok(line) = line else {
+                            break;
+                        };
+                        if sender.send(Event::Line(id, line)).is_err() {
+                            return;
+                        }
+                    }
+                    let _ = sender.send(Event::Disconnected(id));
+                });
+            }
+        });
+        Ok(Self {
+            connect_timeout: Duration::from_secs(60),
+            timeout: Duration::from_secs(60),
+            local_addr,
+            receiver,
+            connections: HashMap::new(),
+            stopped,
+            stats: CoordinatorStats::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn stats(&self) -> CoordinatorStats {
+        self.stats
+    }
+
+    /// connected workers, as far as the coordinator knows
+    pub fn worker_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Blocks until `count` workers are connected, or `connect_timeout` passes.
+    pub fn wait_for_workers(&mut self, count: usize) -> bool {
+        while self.connections.len() < count {
+            match self.receiver.recv_timeout(self.connect_timeout) {
+                Ok(event) => {
+                    self.handle(event, &mut vec![], &mut VecDeque::new());
+                }
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
+    /// Finished (job, evaluations) go to `results`, the work of a disconnected worker to `queue`.
+    fn handle(
+        &mut self,
+        event: Event,
+        results: &mut Vec<(usize, Vec<f64>)>,
+        queue: &mut VecDeque<usize>,
+    ) {
+        match event {
+            Event::Connected(id, stream) => {
+                self.stats.connections += 1;
+                self.connections
+                    .insert(id, Connection { stream, job: None });
+            }
+            Event::Line(id, line) => {
+                let Some(connection) = self.connections.get_mut(&id) else {
+                    return;
+                };
+                if let (Some((response, evaluations)), Some((job, _))) =
+                    (parse_response(&line), connection.job)
+                {
+                    if response == job {
+                        connection.job = None;
+                        self.stats.evaluations += 1;
+                        results.push((job, evaluations));
+                    }
+                }
+            }
+            Event::Disconnected(id) => self.disconnect(id, queue),
+        }
+    }
+
+    fn disconnect(&mut self, id: usize, queue: &mut VecDeque<usize>) {
+        if let Some(connection) = self.connections.remove(&id) {
+            self.stats.disconnects += 1;
+            let _ = connection.stream.shutdown(Shutdown::Both);
+            if let Some((job, _)) = connection.job {
+                self.stats.requeued += 1;
+                queue.push_front(job);
+            }
+        }
+    }
+
+    fn evaluate_requests(&mut self, requests: &[String]) -> Vec<Vec<f64>> {
+        let mut results: Vec<Option<Vec<f64>>> = vec![None; requests.len()];
+        let mut queue: VecDeque<usize> = (0..requests.len()).collect();
+        let mut remaining = requests.len();
+        // 前回のバッチの後に来た接続と切断を先に反映する
+        while let Ok(event) = self.receiver.try_recv() {
+            self.handle(event, &mut vec![], &mut queue);
+        }
+
+        while remaining > 0 {
+            let mut ids: Vec<usize> = self.connections.keys().copied().collect();
+            ids.sort();
+            for id in ids {
+                if queue.is_empty() {
+                    break;
+                }
+                let connection = self.connections.get_mut(&id).unwrap();
+                if connection.job.is_some() {
+                    continue;
+                }
+                let job = queue.pop_front().unwrap();
+                connection.job = Some((job, Instant::now()));
+                let sent = writeln!(connection.stream, "{}", requests[job])
+                    .and_then(|_| connection.stream.flush());
+                if sent.is_err() {
+                    self.disconnect(id, &mut queue);
+                }
+            }
+
+            let deadline = self
+                .connections
+                .values()
+                .filter_map(|connection| connection.job.map(|(_, started)| started + self.timeout))
+                .min();
+            let wait = deadline.map_or(self.connect_timeout, |deadline| {
+                deadline.saturating_duration_since(Instant::now())
+            });
+            let event = match self.receiver.recv_timeout(wait) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) if self.connections.is_empty() => {
+                    panic!("no worker connected to {}", self.local_addr)
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    // 応答しないworkerは切断し、その仕事を他のworkerに回す
+                    let now = Instant::now();
+                    let expired: Vec<usize> = self
+                        .connections
+                        .iter()
+                        .filter(|(_, connection)| {
+                            connection
+                                .job
+                                .is_some_and(|(_, started)| now >= started + self.timeout)
+                        })
+                        .map(|(id, _)| *id)
+                        .collect();
+                    for id in expired {
+                        self.stats.timeouts += 1;
+                        self.disconnect(id, &mut queue);
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => unreachable!(),
+            };
+            let mut finished = vec![];
+            self.handle(event, &mut finished, &mut queue);
+            for (job, evaluations) in finished {
+                if results[job].is_none() {
+                    remaining -= 1;
+                }
+                results[job] = Some(evaluations);
+            }
+        }
+        results.into_iter().map(Option::unwrap).collect()
+    }
+}
+
+impl Drop for Coordinator {
+    fn drop(&mut self) {
+        // workerは接続が閉じると終了する
+        for connection in self.connections.values() {
+            let _ = connection.stream.shutdown(Shutdown::Both);
+        }
+        self.stopped.store(true, Ordering::SeqCst);
+        // acceptを起こして終了させる
+        let _ = TcpStream::connect(self.local_addr);
+    }
+}
+
+impl<I> BatchEvaluator<I> for Coordinator
+where
+    I: individual::Minimum,
+{
+    fn evaluate_batch(&mut self, individuals: &[I]) -> Vec<Vec<f64>> {
+        let requests: Vec<String> = individuals
+            .iter()
+            .enumerate()
+            .map(|(id, individual)| format_request(id, individual))
+            .collect();
+        self.evaluate_requests(&requests)
+    }
+}
+
+/// Worker loop: connects to the coordinator and answers requests with
+/// `evaluate(genes, features)` until the coordinator closes the connection.
+/// Returns the number of evaluations.
+pub fn serve<A, F>(addr: A, mut evaluate: F) -> io::Result<usize>
+where
+    A: ToSocketAddrs,
+    F: FnMut(&[f64], &[Value]) -> Vec<f64>,
+{
+    let stream = TcpStream::connect(addr)?;
+    stream.set_nodelay(true)?;
+    let mut writer = stream.try_clone()?;
+    let mut count = 0;
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => break,
+            Err(e) => return Err(e),
+        };
+        let Ok(request) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        let (Some(id), Some(genes), Some(features)) = (
+            request.get("id").and_then(Value::as_u64),
+            request.get("genes").and_then(Value::as_array),
+            request.get("features").and_then(Value::as_array),
+        ) else {
+            continue;
+        };
+        let genes: Vec<f64> = genes.iter().filter_map(Value::as_f64).collect();
+        let evaluations = evaluate(&genes, features);
+        writeln!(writer, "{}", format_response(id as usize, &evaluations))?;
+        writer.flush()?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// `serve` with `individual::Minimum::evaluate` of the individual made from the genes.
+pub fn run_worker<I, A>(addr: A) -> io::Result<usize>
+where
+    I: individual::ExtMinimum,
+    A: ToSocketAddrs,
+{
+    serve(addr, |genes, _| {
+        let mut individual = I::from_genes(genes.to_vec());
+        individual.set_features(individual.identificate());
+        individual.evaluate()
+    })
+}
@@ -0,0 +1,128 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::group::{self, Configurable};
+use crate::individual;
+
+/// Robust optimization: the evaluations of an individual are aggregated from `copies`
+/// perturbed copies of its genes (gene ± tolerance, clamped to [0.0, 1.0]).
+/// The copies are evaluated like trials, so with `ExtMemoizationDE` they are memoized under
+/// their own features; the aggregated evaluations are not memoized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Robustness {
+    pub copies: usize,
+    /// per gene; a shorter vector repeats its last tolerance
+    pub tolerances: Vec<f64>,
+    pub aggregation: Aggregation,
+    /// The offsets are drawn once from this seed and shared by all individuals (common random
+    /// numbers), so the same genes always get the same evaluations.
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregation {
+    /// mean of every evaluation
+    Mean,
+    /// the worst copy
+    Worst,
+    /// the copy at this quantile (0.0 is the worst, 1.0 the best)
+    Quantile(f64),
+}
+
+impl Robustness {
+    pub fn new(copies: usize, tolerances: Vec<f64>, aggregation: Aggregation, seed: u64) -> Self {
+        assert!(copies >= 1, "Robustness::copies must be at least 1");
+        Self {
+            copies,
+            tolerances,
+            aggregation,
+            seed,
+        }
+    }
+
+    /// genes of the perturbed copies
+    pub fn perturb(&self, genes: &[f64]) -> Vec<Vec<f64>> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        (0..self.copies)
+            .map(|_| {
+                genes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, gene)| {
+                        let tolerance = self
+                            .tolerances
+                            .get(i)
+                            .or(self.tolerances.last())
+                            .copied()
+                            .unwrap_or(0.0);
+                        (gene + tolerance * rng.gen_range(-1.0..=1.0)).clamp(0.0, 1.0)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Effective evaluations of the copies. Empty if a copy has none or a NaN (a failed evaluation).
+pub fn aggregate(evaluations: &[Vec<f64>], aggregation: Aggregation) -> Vec<f64> {
+    if evaluations.is_empty()
+        || evaluations
+            .iter()
+            .any(|e| e.is_empty() || e.iter().any(|x| x.is_nan()))
+    {
+        return vec![];
+    }
+    let sorted = || {
+        let mut sorted: Vec<&Vec<f64>> = evaluations.iter().collect();
+        // NaNは除いてある
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        sorted
+    };
+    match aggregation {
+        Aggregation::Mean => {
+            let n = evaluations.len() as f64;
+            (0..evaluations[0].len())
+                .map(|i| evaluations.iter().map(|e| e[i]).sum::<f64>() / n)
+                .collect()
+        }
+        Aggregation::Worst => sorted()[0].clone(),
+        Aggregation::Quantile(q) => {
+            let index = (q.clamp(0.0, 1.0) * (evaluations.len() - 1) as f64).round() as usize;
+            sorted()[index].clone()
+        }
+    }
+}
+
+/// `evaluate` of the perturbed copies of all `individuals` at once, when `Options::robustness`
+/// is set; otherwise `evaluate` of the individuals themselves.
+pub fn evaluate_robust<I, G, E>(group: &mut G, individuals: &mut [I], evaluate: &mut E)
+where
+    I: individual::ExtMinimum,
+    G: Configurable<I> + group::Minimum<I>,
+    E: FnMut(&mut G, &mut [I]),
+{
+    let Some(robustness) = group.options_as_ref().robustness.clone() else {
+        evaluate(group, individuals);
+        return;
+    };
+    assert!(
+        robustness.copies >= 1,
+        "Robustness::copies must be at least 1"
+    );
+    let mut copies: Vec<I> = individuals
+        .iter()
+        .flat_map(|individual| robustness.perturb(individual.get_genes()))
+        .map(|genes| {
+            let mut copy = I::from_genes(genes);
+            copy.set_features(copy.identificate());
+            copy
+        })
+        .collect();
+    evaluate(group, &mut copies);
+    for (individual, copies) in individuals.iter_mut().zip(copies.chunks(robustness.copies)) {
+        let evaluations: Vec<Vec<f64>> = copies
+            .iter()
+            .map(|copy| copy.get_evaluations().clone())
+            .collect();
+        individual.set_evaluations(aggregate(&evaluations, robustness.aggregation));
+    }
+}
@@ -0,0 +1,316 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use crate::group::{self, Configurable, RunHistory, SurrogateModeling};
+use crate::individual;
+use crate::memo::{ExtMemoQuery, MemoKey, MemoKeying};
+
+/// Pre-screening with a model fitted to the memo: every target makes `candidates` trials
+/// and only the one with the best predicted first evaluation is evaluated.
+/// Features must be numbers (they are the inputs of the model) and the memo keys must not be
+/// `MemoKeying::Hash128`; with hashed memo keys the first candidate is used.
+/// It needs the memo, so `method::ExtConfiguredDE` refuses it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Surrogate {
+    pub candidates: usize,
+    /// refit the model every `retrain_interval` epochs
+    pub retrain_interval: usize,
+    /// at most `max_points` memo entries, spread evenly over their ranks from the best to the
+    /// worst, are used for fitting. The best ones alone would leave the poor regions unknown
+    pub max_points: usize,
+}
+
+/// Gaussian radial basis function interpolation of the first evaluation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rbf {
+    centers: Vec<Vec<f64>>,
+    weights: Vec<f64>,
+    width: f64,
+    /// prediction far from every center; the worst value, so unknown regions are not preferred
+    offset: f64,
+}
+
+impl Rbf {
+    /// `None` without points
+    pub fn fit(points: &[(Vec<f64>, f64)]) -> Option<Self> {
+        if points.is_empty() {
+            return None;
+        }
+        let offset = points.iter().map(|(_, y)| *y).fold(f64::INFINITY, f64::min);
+        // 幅は中心間距離の中央値
+        let mut distances: Vec<f64> = vec![];
+        for (i, (a, _)) in points.iter().enumerate() {
+            for (b, _) in points[i + 1..].iter() {
+                distances.push(euclidean(a, b));
+            }
+        }
+        distances.retain(|d| *d > 0.0);
+        distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let width = distances.get(distances.len() / 2).copied().unwrap_or(1.0);
+
+        let n = points.len();
+        let mut matrix = vec![vec![0.0; n]; n];
+        for i in 0..n {
+            for j in 0..n {
+                matrix[i][j] = gaussian(euclidean(&points[i].0, &points[j].0), width);
+            }
+            // 正則化. 同じ点が複数あっても解ける
+            matrix[i][i] += 1e-8;
+        }
+        let targets: Vec<f64> = points.iter().map(|(_, y)| y - offset).collect();
+        let weights = solve_cholesky(matrix, targets)?;
+        Some(Self {
+            centers: points.iter().map(|(x, _)| x.clone()).collect(),
+            weights,
+            width,
+            offset,
+        })
+    }
+
+    pub fn predict(&self, x: &[f64]) -> f64 {
+        self.offset
+            + self
+                .centers
+                .iter()
+                .zip(self.weights.iter())
+                .map(|(center, weight)| weight * gaussian(euclidean(center, x), self.width))
+                .sum::<f64>()
+    }
+}
+
+fn euclidean(a: &[f64], b: &[f64]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b) * (a - b))
+        .sum::<f64>()
+        .sqrt()
+}
+
+fn gaussian(distance: f64, width: f64) -> f64 {
+    (-(distance / width).powi(2)).exp()
+}
+
+/// `matrix` must be symmetric positive definite
+fn solve_cholesky(matrix: Vec<Vec<f64>>, b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    let mut l = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            if i == j {
+                let d = matrix[i][i] - sum;
+                if d <= 0.0 {
+                    return None;
+                }
+                l[i][i] = d.sqrt();
+            } else {
+                l[i][j] = (matrix[i][j] - sum) / l[j][j];
+            }
+        }
+    }
+    let mut y = vec![0.0; n];
+    for i in 0..n {
+        let sum: f64 = (0..i).map(|k| l[i][k] * y[k]).sum();
+        y[i] = (b[i] - sum) / l[i][i];
+    }
+    let mut x = vec![0.0; n];
+    for i in (0..n).rev() {
+        let sum: f64 = (i + 1..n).map(|k| l[k][i] * x[k]).sum();
+        x[i] = (y[i] - sum) / l[i][i];
+    }
+    Some(x)
+}
+
+/// Accuracy of one model, measured on the memo entries added until it was replaced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Training {
+    pub epoch: usize,
+    /// points the model was fitted to
+    pub points: usize,
+    /// entries it was tested on, 0 until the next retraining
+    pub tested: usize,
+    pub mean_absolute_error: f64,
+    pub root_mean_squared_error: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SurrogateReport {
+    pub trainings: Vec<Training>,
+}
+
+impl SurrogateReport {
+    /// over all tested entries; `None` before any test
+    pub fn mean_absolute_error(&self) -> Option<f64> {
+        let tested: usize = self.trainings.iter().map(|t| t.tested).sum();
+        (tested > 0).then(|| {
+            self.trainings
+                .iter()
+                .map(|t| t.mean_absolute_error * t.tested as f64)
+                .sum::<f64>()
+                / tested as f64
+        })
+    }
+}
+
+/// The fitted model and what it was fitted to. Kept in the group, not saved.
+#[derive(Debug, Clone)]
+pub struct SurrogateState<F> {
+    model: Option<Rbf>,
+    trained_epoch: Option<usize>,
+    /// memo keys when the model was fitted
+    known_keys: HashSet<MemoKey<F>>,
+    report: SurrogateReport,
+}
+
+impl<F> Default for SurrogateState<F> {
+    fn default() -> Self {
+        Self {
+            model: None,
+            trained_epoch: None,
+            known_keys: HashSet::new(),
+            report: SurrogateReport::default(),
+        }
+    }
+}
+
+impl<F> SurrogateState<F> {
+    pub fn model(&self) -> Option<&Rbf> {
+        self.model.as_ref()
+    }
+
+    pub fn report(&self) -> &SurrogateReport {
+        &self.report
+    }
+}
+
+pub trait ExtSurrogate<I> {
+    fn get_surrogate_report<'a>(&'a self) -> &'a SurrogateReport
+    where
+        I: 'a;
+    /// Fit the model to the memo now; entries added since the last fit are used to measure
+    /// the accuracy of the previous model.
+    fn retrain_surrogate(&mut self, max_points: usize);
+    /// Index of the most promising candidate. Retrains the model when it is due.
+    fn screen_candidates(&mut self, candidates: &[I]) -> usize;
+}
+
+impl<I, G> ExtSurrogate<I> for G
+where
+    I: individual::ExtMinimum,
+    G: group::ExtMemoization<I> + Configurable<I> + RunHistory<I> + SurrogateModeling<I>,
+{
+    fn get_surrogate_report<'a>(&'a self) -> &'a SurrogateReport
+    where
+        I: 'a,
+    {
+        self.surrogate_as_ref().report()
+    }
+
+    fn retrain_surrogate(&mut self, max_points: usize) {
+        let epoch = self.history_as_ref().epochs;
+        let inputs = |group: &G, key: &MemoKey<I::Feature>| -> Option<Vec<f64>> {
+            // hashされたkeyからは入力を復元できない
+            numeric::<I>(&group.parse_memo_key(key)?)
+        };
+
+        // 前回のモデルを、その後に追加されたentryで検証する
+        let mut errors = vec![];
+        if let Some(model) = self.surrogate_as_ref().model() {
+            let known_keys = &self.surrogate_as_ref().known_keys;
+            for (key, value) in self.memo_as_ref().iter() {
+                if known_keys.contains(key) {
+                    continue;
+                }
+                if let (Some(x), Some(y)) = (inputs(self, key), value.first()) {
+                    errors.push(model.predict(&x) - y);
+                }
+            }
+        }
+        if let Some(last) = self.surrogate_as_mut().report.trainings.last_mut() {
+            if !errors.is_empty() {
+                let n = errors.len() as f64;
+                last.tested = errors.len();
+                last.mean_absolute_error = errors.iter().map(|e| e.abs()).sum::<f64>() / n;
+                last.root_mean_squared_error =
+                    (errors.iter().map(|e| e * e).sum::<f64>() / n).sqrt();
+            }
+        }
+
+        let known_keys = self.memo_as_ref().keys().cloned().collect();
+        let mut ranked = vec![];
+        for (key, value) in self.memo_as_ref().iter() {
+            if let (Some(x), Some(y)) = (inputs(self, key), value.first()) {
+                ranked.push((x, *y));
+            }
+        }
+        // 良い順. 同じ評価値はinputsの順にして、memoの走査順に依らないようにする
+        ranked.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then_with(|| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+        });
+        let points = spread(ranked, max_points);
+        let model = Rbf::fit(&points);
+        let state = self.surrogate_as_mut();
+        state.trained_epoch = Some(epoch);
+        state.known_keys = known_keys;
+        if model.is_some() {
+            state.report.trainings.push(Training {
+                epoch,
+                points: points.len(),
+                tested: 0,
+                mean_absolute_error: 0.0,
+                root_mean_squared_error: 0.0,
+            });
+        }
+        state.model = model;
+    }
+
+    fn screen_candidates(&mut self, candidates: &[I]) -> usize {
+        let Some(surrogate) = self.options_as_ref().surrogate.clone() else {
+            return 0;
+        };
+        if *self.memo_keying_as_ref() == MemoKeying::Hash128 {
+            return 0;
+        }
+        let epoch = self.history_as_ref().epochs;
+        let due = match self.surrogate_as_ref().trained_epoch {
+            Some(trained) => epoch >= trained + surrogate.retrain_interval.max(1),
+            None => true,
+        };
+        if due {
+            self.retrain_surrogate(surrogate.max_points);
+        }
+        let Some(model) = self.surrogate_as_ref().model() else {
+            return 0;
+        };
+        let predictions: Vec<f64> = candidates
+            .iter()
+            .map(|candidate| {
+                numeric::<I>(candidate.get_features()).map_or(f64::MIN, |x| model.predict(&x))
+            })
+            .collect();
+        (0..candidates.len())
+            .max_by(|a, b| predictions[*a].partial_cmp(&predictions[*b]).unwrap())
+            .unwrap()
+    }
+}
+
+/// `k` elements at evenly spaced positions, the first and the last included
+fn spread<T>(items: Vec<T>, k: usize) -> Vec<T> {
+    let len = items.len();
+    if len <= k {
+        return items;
+    }
+    let mut items: Vec<Option<T>> = items.into_iter().map(Some).collect();
+    (0..k)
+        .map(|j| items[j * (len - 1) / (k - 1).max(1)].take().unwrap())
+        .collect()
+}
+
+/// features parsed as f64
+fn numeric<I: individual::Minimum>(features: &[I::Feature]) -> Option<Vec<f64>> {
+    features
+        .iter()
+        .map(|feature| feature.to_string().parse().ok())
+        .collect()
+}
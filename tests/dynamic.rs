@@ -0,0 +1,181 @@
+use std::cell::Cell;
+
+use serde::{Deserialize, Serialize};
+use ys_differential_evolution::dynamic::*;
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::memo::{ExtMemoLog, ExtMemoQuery};
+use ys_differential_evolution::method;
+use ys_differential_evolution::method::ExtMemoizationDE;
+
+thread_local! {
+    /// the live data; every test runs on its own thread
+    static TARGET: Cell<i64> = const { Cell::new(-5) };
+}
+
+fn truth(features: &[i64]) -> f64 {
+    let target = TARGET.with(Cell::get);
+    features
+        .iter()
+        .fold(0.0, |a, f| a - ((f - target) * (f - target)) as f64)
+}
+
+/// -sum((features - TARGET)^2)
+fn live(features: &[i64]) -> Vec<f64> {
+    vec![truth(features)]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Grid {
+    genes: Vec<f64>,
+    features: Vec<i64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Grid {
+    type Feature = i64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes
+            .iter()
+            .map(|x| ((*x - 0.5) * 20.0).round() as i64)
+            .collect()
+    }
+
+    fn evaluate(&self) -> Vec<f64> {
+        live(&self.features)
+    }
+}
+
+fn run(dynamic: Option<Dynamic>) -> group::Group<Grid> {
+    TARGET.with(|t| t.set(-5));
+    let mut g = group::Group::<Grid>::from_shape(20, 2, 0);
+    g.options_as_mut().dynamic = dynamic;
+    g.advance_epoch(20, "rand", 1, 0.5, 0.5);
+    assert_eq!(g.get_best().1.features, vec![-5, -5]);
+    TARGET.with(|t| t.set(5));
+    g.advance_epoch(50, "rand", 1, 0.5, 0.5);
+    g
+}
+
+fn stale_entries(g: &group::Group<Grid>) -> usize {
+    g.memo_as_ref()
+        .iter()
+        .filter(|(key, value)| {
+            let features = g.parse_memo_key(key).unwrap();
+            **value != vec![truth(&features)]
+        })
+        .count()
+}
+
+#[test]
+fn stale_memo_without_dynamic_mode() {
+    let g = run(None);
+    assert!(stale_entries(&g) > 0);
+}
+
+#[test]
+fn changes_are_detected_and_answered() {
+    for response in [
+        Response::Reevaluate,
+        Response::RandomImmigrants { fraction: 0.5 },
+        Response::Hypermutation { step: 0.3 },
+    ] {
+        let g = run(Some(Dynamic {
+            interval: 5,
+            sentinels: 3,
+            threshold: 0.5,
+            response,
+        }));
+        let changes = &g.history_as_ref().changes;
+        assert_eq!(changes.len(), 1, "{:?}", response);
+        assert_eq!(changes[0].epoch, 25);
+        assert!(changes[0].drift >= 200.0);
+
+        assert_eq!(stale_entries(&g), 0);
+        // a converged population needs new diversity to follow the optimum
+        if response != Response::Reevaluate {
+            assert_eq!(g.get_best().1.features, vec![5, 5], "{:?}", response);
+        }
+        assert!(g
+            .get_individuals()
+            .iter()
+            .all(|i| i.evals == vec![truth(&i.features)]));
+    }
+}
+
+#[test]
+fn reopened_log_forgets_the_old_environment() {
+    let log = std::env::temp_dir().join(format!("ys_de_{}_dynamic.log", std::process::id()));
+    let log = log.to_str().unwrap();
+    let _ = std::fs::remove_file(log);
+
+    TARGET.with(|t| t.set(-5));
+    let mut g = group::Group::<Grid>::from_shape(20, 2, 0);
+    g.options_as_mut().dynamic = Some(Dynamic {
+        interval: 5,
+        sentinels: 3,
+        threshold: 0.5,
+        response: Response::RandomImmigrants { fraction: 0.5 },
+    });
+    g.open_memo_log(log).unwrap();
+    g.advance_epoch(20, "rand", 1, 0.5, 0.5);
+    TARGET.with(|t| t.set(5));
+    g.advance_epoch(20, "rand", 1, 0.5, 0.5);
+    assert_eq!(g.history_as_ref().changes.len(), 1);
+    g.close_memo_log();
+
+    let mut reopened = group::Group::<Grid>::from_shape(20, 2, 0);
+    reopened.open_memo_log(log).unwrap();
+    assert!(!reopened.memo_as_ref().is_empty());
+    assert_eq!(stale_entries(&reopened), 0);
+    assert_eq!(reopened.memo_as_ref(), g.memo_as_ref());
+    reopened.close_memo_log();
+    std::fs::remove_file(log).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "Options::dynamic needs the memo")]
+fn dynamic_mode_is_refused_without_a_memo() {
+    let mut g = group::Group::<Grid>::from_shape(20, 2, 0);
+    g.options_as_mut().dynamic = Some(Dynamic {
+        interval: 5,
+        sentinels: 3,
+        threshold: 0.5,
+        response: Response::Reevaluate,
+    });
+    method::ExtConfiguredDE::advance_epoch(&mut g, 1, "rand", 1, 0.5, 0.5);
+}
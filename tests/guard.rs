@@ -0,0 +1,178 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use ys_differential_evolution::evaluator::{
+    ExtBatchEvaluation, ExtEvaluationTimeout, FailurePolicy, Guard,
+};
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::memo::ExtMemoQuery;
+use ys_differential_evolution::method::ExtMemoizationDE;
+
+/// -sum(features^2)
+fn fitness(features: &[i64]) -> Vec<f64> {
+    vec![features.iter().fold(0.0, |a, b| a - (b * b) as f64)]
+}
+
+/// features that already failed once
+static FLAKY: Mutex<Vec<Vec<i64>>> = Mutex::new(vec![]);
+
+/// panics at features[0] == 3, hangs at -3, and fails once at 5
+fn flaky_fitness(features: &[i64]) -> Vec<f64> {
+    match features[0] {
+        3 => panic!("solver diverged"),
+        -3 => std::thread::sleep(Duration::from_secs(2)),
+        5 => {
+            let mut flaky = FLAKY.lock().unwrap();
+            if !flaky.iter().any(|f| f == features) {
+                flaky.push(features.to_vec());
+                drop(flaky);
+                panic!("license server busy");
+            }
+        }
+        _ => {}
+    }
+    fitness(features)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Grid {
+    genes: Vec<f64>,
+    features: Vec<i64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Grid {
+    type Feature = i64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes
+            .iter()
+            .map(|x| ((*x - 0.5) * 20.0).round() as i64)
+            .collect()
+    }
+
+    fn evaluate(&self) -> Vec<f64> {
+        flaky_fitness(&self.features)
+    }
+}
+
+fn guarded(policy: FailurePolicy) -> group::Group<Grid> {
+    let mut g = group::Group::<Grid>::from_shape(20, 4, 2);
+    g.options_as_mut().guard = Some(Guard { retries: 1, policy });
+    g.set_evaluation_timeout(Some(Duration::from_millis(100)));
+    g
+}
+
+#[test]
+fn failed_evaluations_are_isolated_and_logged() {
+    let mut g = guarded(FailurePolicy::Worst);
+    g.advance_epoch(30, "rand", 1, 0.5, 0.5);
+
+    let failures = &g.history_as_ref().failures;
+    assert!(!failures.is_empty());
+    for failure in failures {
+        assert_eq!(failure.attempts, 2);
+        assert!(failure.key.starts_with("3,") || failure.key.starts_with("-3,"));
+        if failure.key.starts_with("3,") {
+            assert!(failure.reason.contains("solver diverged"));
+        } else {
+            assert!(failure.reason.contains("timed out"));
+        }
+        // 失敗はmemoに残らない
+        assert!(g
+            .memo_as_ref()
+            .keys()
+            .all(|key| key.to_string() != failure.key));
+    }
+    // one retry is enough for the flaky features
+    assert!(g
+        .memo_as_ref()
+        .keys()
+        .any(|key| key.to_string().starts_with("5,")));
+    assert!(g
+        .get_individuals()
+        .iter()
+        .all(|i| i.evals == fitness(&i.features) || i.evals == vec![f64::MIN]));
+    assert!(g.get_best().1.evals[0] > f64::MIN);
+}
+
+#[test]
+fn resample_replaces_failed_trials() {
+    let mut g = guarded(FailurePolicy::Resample { max_resamples: 5 });
+    g.advance_epoch(30, "rand", 1, 0.5, 0.5);
+    assert!(!g.history_as_ref().failures.is_empty());
+    assert!(g
+        .get_individuals()
+        .iter()
+        .all(|i| i.evals == fitness(&i.features)));
+}
+
+#[test]
+fn abort_after_max_failures_keeps_the_memo() {
+    let mut g = guarded(FailurePolicy::Abort { max_failures: 2 });
+    g.advance_epoch(100, "rand", 1, 0.5, 0.5);
+    assert!(g.history_as_ref().aborted);
+    assert!(g.history_as_ref().epochs < 100);
+    assert!(g.history_as_ref().failures.len() >= 2);
+    assert!(!g.memo_as_ref().is_empty());
+}
+
+#[test]
+fn panicking_batch_is_split_to_find_the_culprits() {
+    let mut g = guarded(FailurePolicy::Worst);
+    g.set_batch_evaluator(Some(Box::new(|individuals: &[Grid]| {
+        individuals
+            .iter()
+            .map(|i| {
+                assert_ne!(i.features[0], 3, "batch solver diverged");
+                fitness(&i.features)
+            })
+            .collect::<Vec<Vec<f64>>>()
+    })));
+    g.advance_epoch(30, "rand", 1, 0.5, 0.5);
+
+    let failures = &g.history_as_ref().failures;
+    assert!(!failures.is_empty());
+    assert!(failures
+        .iter()
+        .all(|f| f.key.starts_with("3,") && f.reason.contains("batch solver diverged")));
+    assert!(g
+        .memo_as_ref()
+        .iter()
+        .all(|(key, value)| { *value == fitness(&g.parse_memo_key(key).unwrap()) }));
+}
@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::init::Initialization;
+use ys_differential_evolution::method::ExtMemoizationDE;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Shifted {
+    genes: Vec<f64>,
+    features: Vec<f64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Shifted {
+    type Feature = f64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes.clone()
+    }
+
+    /// optimum at (0.9, 0.9, ...)
+    fn evaluate(&self) -> Vec<f64> {
+        vec![self
+            .features
+            .iter()
+            .fold(0.0, |a, b| a - (b - 0.9) * (b - 0.9))]
+    }
+}
+
+#[test]
+fn from_seeds_keeps_known_solutions() {
+    let known = vec![vec![0.9; 5], vec![0.8; 5]];
+    let g = group::Group::<Shifted>::from_seeds(10, 5, known.clone(), 0);
+    assert_eq!(g.get_individuals().len(), 10);
+    assert_eq!(g.get_individuals()[0].genes, known[0]);
+    assert_eq!(g.get_individuals()[1].genes, known[1]);
+
+    // the random part is the same as from_shape
+    let shape = group::Group::<Shifted>::from_shape(8, 5, 0);
+    for (seeded, random) in g.get_individuals()[2..].iter().zip(shape.get_individuals()) {
+        assert_eq!(seeded.genes, random.genes);
+    }
+
+    let mut g = g;
+    g.advance_epoch(1, "rand", 1, 0.5, 0.5);
+    assert_eq!(g.get_best().1.genes, known[0]);
+}
+
+fn strata(g: &group::Group<Shifted>, dimension: usize) -> Vec<usize> {
+    let len = g.get_individuals().len();
+    let mut strata: Vec<usize> = g
+        .get_individuals()
+        .iter()
+        .map(|i| (i.genes[dimension] * len as f64) as usize)
+        .collect();
+    strata.sort();
+    strata
+}
+
+#[test]
+fn stratified_initializations() {
+    for initialization in [Initialization::LatinHypercube, Initialization::Sobol] {
+        // 2^4 points of a Sobol sequence fill every 1/16 interval of every dimension
+        let g = group::Group::<Shifted>::from_shape_with(16, 40, 0, initialization);
+        for dimension in 0..40 {
+            assert_eq!(strata(&g, dimension), (0..16).collect::<Vec<_>>());
+        }
+    }
+
+    let g = group::Group::<Shifted>::from_shape_with(20, 5, 0, Initialization::Halton);
+    for individual in g.get_individuals() {
+        assert!(individual.genes.iter().all(|g| (0.0..1.0).contains(g)));
+    }
+    // the first dimension uses base 2, the first 16 points are stratified as well
+    let g = group::Group::<Shifted>::from_shape_with(16, 5, 0, Initialization::Halton);
+    assert_eq!(strata(&g, 0), (0..16).collect::<Vec<_>>());
+}
+
+#[test]
+fn initializations_are_deterministic() {
+    let genes = |g: &group::Group<Shifted>| -> Vec<Vec<f64>> {
+        g.get_individuals()
+            .iter()
+            .map(|i| i.genes.clone())
+            .collect()
+    };
+    assert_eq!(
+        genes(&group::Group::<Shifted>::from_shape(10, 5, 3)),
+        genes(&group::Group::<Shifted>::from_shape_with(
+            10,
+            5,
+            3,
+            Initialization::Uniform
+        ))
+    );
+    for initialization in [
+        Initialization::LatinHypercube,
+        Initialization::Sobol,
+        Initialization::Halton,
+    ] {
+        let a = group::Group::<Shifted>::from_shape_with(10, 5, 3, initialization);
+        let b = group::Group::<Shifted>::from_shape_with(10, 5, 3, initialization);
+        let c = group::Group::<Shifted>::from_shape_with(10, 5, 4, initialization);
+        assert_eq!(genes(&a), genes(&b));
+        assert_ne!(genes(&a), genes(&c));
+    }
+}
+
+#[test]
+fn opposition_initialization_keeps_the_better_half() {
+    let uniform = group::Group::<Shifted>::from_shape(10, 5, 0);
+    let mut opposition = group::Group::<Shifted>::from_shape(10, 5, 0);
+    opposition.options_as_mut().initial_opposition = true;
+    opposition.advance_epoch(0, "rand", 1, 0.5, 0.5);
+    assert_eq!(opposition.get_individuals().len(), 10);
+    // the opposites are evaluated through the memo like any other individual
+    assert_eq!(opposition.get_memo_stats().misses, 20);
+
+    let mut candidates: Vec<Vec<f64>> = vec![];
+    for individual in uniform.get_individuals() {
+        candidates.push(individual.genes.clone());
+        candidates.push(individual.genes.iter().map(|g| 1.0 - g).collect());
+    }
+    let score = |genes: &Vec<f64>| genes.iter().fold(0.0, |a, b| a - (b - 0.9) * (b - 0.9));
+    let mut scores: Vec<f64> = candidates.iter().map(score).collect();
+    scores.sort_by(|a, b| b.partial_cmp(a).unwrap());
+
+    let kept: Vec<f64> = opposition
+        .get_individuals()
+        .iter()
+        .map(|i| i.evals[0])
+        .collect();
+    assert_eq!(kept, scores[..10]);
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::island::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Car {
+    genes: Vec<f64>,
+    features: Vec<f64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Car {
+    type Feature = f64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes
+            .iter()
+            .map(|x| ((*x - 0.5) * 1000.0).round().abs() / 1000.0)
+            .collect()
+    }
+
+    fn evaluate(&self) -> Vec<f64> {
+        vec![self.features.iter().fold(0.0, |a, b| a - b * b)]
+    }
+}
+
+fn archipelago(topology: Topology, migration: Migration, shared_memo: bool) -> Archipelago<Car> {
+    let islands = (0..4)
+        .map(|seed| group::Group::<Car>::from_shape(10, 5, seed))
+        .collect();
+    let strategies = (0..4)
+        .map(|i| Strategy {
+            best_or_rand: if i % 2 == 0 { "rand" } else { "best" }.to_string(),
+            difference_vector_count: 1,
+            f_scale: 0.3 + 0.1 * i as f64,
+            crossover_rate: 0.5,
+        })
+        .collect();
+    let mut archipelago = Archipelago::new(islands, strategies, 0);
+    archipelago.topology = topology;
+    archipelago.migration = migration;
+    archipelago.migration_interval = 5;
+    archipelago.migrants = 2;
+    archipelago.shared_memo = shared_memo;
+    archipelago
+}
+
+fn genes(archipelago: &Archipelago<Car>) -> Vec<Vec<Vec<f64>>> {
+    archipelago
+        .islands
+        .iter()
+        .map(|g| {
+            g.get_individuals()
+                .iter()
+                .map(|i| i.genes.clone())
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn islands_run_deterministically() {
+    for topology in [Topology::Ring, Topology::FullyConnected, Topology::Random] {
+        for migration in [
+            Migration::BestReplacesWorst,
+            Migration::RandomReplacesRandom,
+        ] {
+            let mut a = archipelago(topology, migration, false);
+            let mut b = archipelago(topology, migration, false);
+            a.advance_epoch(23);
+            // a different split of the same number of epochs
+            b.advance_epoch(7);
+            b.advance_epoch(16);
+            assert_eq!(genes(&a), genes(&b));
+        }
+    }
+}
+
+#[test]
+fn ring_migration_moves_the_best() {
+    let mut a = archipelago(Topology::Ring, Migration::BestReplacesWorst, false);
+    let mut without_migration = archipelago(Topology::Ring, Migration::BestReplacesWorst, false);
+    without_migration.migrants = 0;
+    a.advance_epoch(5);
+    without_migration.advance_epoch(5);
+
+    for (i, island) in a.islands.iter().enumerate() {
+        let previous = without_migration.islands[(i + 3) % 4].get_best().1;
+        assert!(island
+            .get_individuals()
+            .iter()
+            .any(|individual| individual.genes == previous.genes));
+    }
+}
+
+#[test]
+fn shared_memo_pools_evaluations() {
+    let mut a = archipelago(Topology::Ring, Migration::BestReplacesWorst, true);
+    a.advance_epoch(10);
+    let memo = a.islands[0].memo_as_ref();
+    assert!(a.islands.iter().all(|g| g.memo_as_ref() == memo));
+
+    let (island, index, best) = a.get_best();
+    assert_eq!(a.islands[island].get_individuals()[index].genes, best.genes);
+}
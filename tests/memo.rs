@@ -0,0 +1,541 @@
+use std::fs::{read_to_string, remove_file, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use ys_differential_evolution::evaluator::ExtBatchEvaluation;
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::memo::*;
+use ys_differential_evolution::method::ExtMemoizationDE;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Grid {
+    genes: Vec<f64>,
+    features: Vec<i64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Grid {
+    type Feature = i64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes
+            .iter()
+            .map(|x| ((*x - 0.5) * 20.0).round() as i64)
+            .collect()
+    }
+
+    fn evaluate(&self) -> Vec<f64> {
+        vec![self.features.iter().fold(0.0, |a, b| a - (b * b) as f64)]
+    }
+
+    fn encode(features: &[Self::Feature]) -> Option<Vec<f64>> {
+        Some(features.iter().map(|f| *f as f64 / 20.0 + 0.5).collect())
+    }
+}
+
+fn temp_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("ys_de_{}_{}", std::process::id(), name));
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn memo_log_survives_crash() {
+    let log = temp_path("memo.log");
+    let _ = remove_file(&log);
+
+    let mut g = group::Group::<Grid>::from_shape(10, 4, 0);
+    g.open_memo_log(&log).unwrap();
+    g.advance_epoch(20, "rand", 1, 0.5, 0.5);
+    let expected = g.memo_as_ref().clone();
+    // simulate a crash: no save, no close, and a torn last line
+    drop(g);
+    let mut file = OpenOptions::new().append(true).open(&log).unwrap();
+    file.write_all(b"1,2,3,4:-3").unwrap();
+
+    let mut g = group::Group::<Grid>::from_shape(10, 4, 0);
+    g.open_memo_log(&log).unwrap();
+    assert_eq!(g.memo_as_ref(), &expected);
+
+    g.advance_epoch(20, "rand", 1, 0.5, 0.5);
+    g.compact_memo_log();
+    g.close_memo_log();
+    let lines = read_to_string(&log).unwrap().lines().count();
+    assert_eq!(lines, g.memo_as_ref().len());
+
+    let mut loaded = group::Group::<Grid>::new();
+    loaded.load_memo_from_csv(&log);
+    assert_eq!(loaded.memo_as_ref(), g.memo_as_ref());
+    remove_file(&log).unwrap();
+}
+
+#[test]
+fn bounded_memo_log_keeps_evicted_entries() {
+    let log = temp_path("bounded.log");
+    let _ = remove_file(&log);
+
+    let mut unbounded = group::Group::<Grid>::from_shape(10, 4, 5);
+    unbounded.advance_epoch(30, "rand", 1, 0.5, 0.5);
+
+    let mut bounded = group::Group::<Grid>::from_shape(10, 4, 5);
+    bounded.set_memo_bound(Some(MemoBound {
+        capacity: Some(20),
+        byte_budget: None,
+        eviction: Eviction::Lru,
+    }));
+    bounded.open_memo_log(&log).unwrap();
+    bounded.advance_epoch(30, "rand", 1, 0.5, 0.5);
+    bounded.compact_memo_log();
+    bounded.close_memo_log();
+    assert!(bounded.memo_as_ref().len() <= 20);
+
+    let lines = read_to_string(&log).unwrap().lines().count();
+    assert_eq!(lines, unbounded.memo_as_ref().len());
+    let mut loaded = group::Group::<Grid>::new();
+    loaded.load_memo_from_csv(&log);
+    assert_eq!(loaded.memo_as_ref(), unbounded.memo_as_ref());
+    remove_file(&log).unwrap();
+}
+
+#[test]
+fn hashed_memo_keys_match_joined_keys() {
+    let mut joined = group::Group::<Grid>::from_shape(10, 4, 3);
+    joined.advance_epoch(30, "rand", 1, 0.5, 0.5);
+
+    let mut hashed = group::Group::<Grid>::from_shape(10, 4, 3);
+    hashed.set_memo_keying(MemoKeying::Hash128);
+    hashed.advance_epoch(30, "rand", 1, 0.5, 0.5);
+
+    assert_eq!(joined.get_best().1.genes, hashed.get_best().1.genes);
+    assert!(hashed
+        .memo_as_ref()
+        .keys()
+        .all(|key| matches!(key, MemoKey::Hash128(_))));
+
+    joined.set_memo_keying(MemoKeying::Hash128);
+    assert_eq!(joined.memo_as_ref(), hashed.memo_as_ref());
+}
+
+#[test]
+fn feature_memo_keys_match_joined_keys() {
+    let mut joined = group::Group::<Grid>::from_shape(10, 4, 3);
+    joined.advance_epoch(30, "rand", 1, 0.5, 0.5);
+
+    let mut featured = group::Group::<Grid>::from_shape(10, 4, 3);
+    featured.set_feature_memo_keying();
+    featured.advance_epoch(30, "rand", 1, 0.5, 0.5);
+
+    assert_eq!(joined.get_best().1.genes, featured.get_best().1.genes);
+    let best = featured.get_best().1.features.clone();
+    assert!(matches!(featured.memo_key(&best), MemoKey::Features(..)));
+    assert_eq!(featured.get_memo(&best), joined.get_memo(&best));
+    assert_eq!(featured.top_memo(5), {
+        joined.set_feature_memo_keying();
+        joined.top_memo(5)
+    });
+    assert_eq!(joined.memo_as_ref(), featured.memo_as_ref());
+
+    // written joined, so they load into a joined memo
+    let csv = temp_path("features.csv");
+    featured.save_memo_to_csv(&csv);
+    assert_eq!(read_memo_keying(&csv), MemoKeying::Joined);
+    let mut loaded = group::Group::<Grid>::new();
+    loaded.load_memo_from_csv(&csv);
+    assert_eq!(loaded.get_memo(&best), featured.get_memo(&best));
+    remove_file(&csv).unwrap();
+}
+
+#[test]
+fn memo_files_record_their_keying() {
+    let csv = temp_path("keying.csv");
+    let log = temp_path("keying.log");
+    let _ = remove_file(&log);
+
+    let mut joined = group::Group::<Grid>::from_shape(10, 4, 3);
+    joined.open_memo_log(&log).unwrap();
+    joined.advance_epoch(10, "rand", 1, 0.5, 0.5);
+    joined.save_memo_to_csv(&csv);
+    assert_eq!(read_memo_keying(&csv), MemoKeying::Joined);
+
+    // joined files are hashed on load
+    let mut hashed = group::Group::<Grid>::new();
+    hashed.set_memo_keying(MemoKeying::Hash128);
+    hashed.load_memo_from_csv(&csv);
+    let mut merged = group::Group::<Grid>::new();
+    merged.set_memo_keying(MemoKeying::Hash128);
+    merged.merge_memo_files(&[&csv], MergePolicy::Fail).unwrap();
+    assert_eq!(hashed.memo_as_ref(), merged.memo_as_ref());
+    assert_eq!(hashed.memo_as_ref().len(), joined.memo_as_ref().len());
+    // a log is rewritten, so that later appends share its keying
+    let mut reopened = group::Group::<Grid>::new();
+    reopened.set_memo_keying(MemoKeying::Hash128);
+    reopened.open_memo_log(&csv).unwrap();
+    reopened.close_memo_log();
+    assert_eq!(read_memo_keying(&csv), MemoKeying::Hash128);
+    assert_eq!(reopened.memo_as_ref(), hashed.memo_as_ref());
+
+    // switching while a log is open
+    joined.set_memo_keying(MemoKeying::Hash128);
+    assert_eq!(read_memo_keying(&log), MemoKeying::Hash128);
+    joined.advance_epoch(10, "rand", 1, 0.5, 0.5);
+    joined.close_memo_log();
+    assert!(hashed
+        .memo_as_ref()
+        .iter()
+        .all(|(key, value)| joined.memo_as_ref().get(key) == Some(value)));
+    let mut logged = group::Group::<Grid>::new();
+    logged.set_memo_keying(MemoKeying::Hash128);
+    logged.open_memo_log(&log).unwrap();
+    logged.close_memo_log();
+    assert_eq!(logged.memo_as_ref(), joined.memo_as_ref());
+
+    remove_file(&csv).unwrap();
+    remove_file(&log).unwrap();
+}
+
+#[test]
+fn bounded_memo_does_not_change_results() {
+    let mut unbounded = group::Group::<Grid>::from_shape(10, 4, 5);
+    unbounded.advance_epoch(50, "rand", 1, 0.5, 0.5);
+
+    for eviction in [Eviction::Lru, Eviction::Lfu] {
+        let mut bounded = group::Group::<Grid>::from_shape(10, 4, 5);
+        bounded.set_memo_bound(Some(MemoBound {
+            capacity: Some(20),
+            byte_budget: Some(
+                20 * MemoCache::entry_bytes(
+                    &MemoKey::<i64>::Joined("-10,-10,-10,-10".to_string()),
+                    &[0.0],
+                ),
+            ),
+            eviction,
+        }));
+        bounded.advance_epoch(50, "rand", 1, 0.5, 0.5);
+
+        let genes = |g: &group::Group<Grid>| -> Vec<Vec<f64>> {
+            g.get_individuals()
+                .iter()
+                .map(|i| i.genes.clone())
+                .collect()
+        };
+        assert_eq!(genes(&unbounded), genes(&bounded));
+        assert!(bounded.memo_as_ref().len() <= 20);
+        let stats = bounded.get_memo_stats();
+        assert!(stats.evictions > 0);
+        assert_eq!(stats.hits + stats.misses, 10 * 51);
+        assert_eq!(
+            stats.insertions - stats.evictions,
+            bounded.memo_as_ref().len()
+        );
+        assert!(stats.misses > unbounded.get_memo_stats().misses);
+    }
+}
+
+#[test]
+fn bounded_memo_edits_go_through_the_cache() {
+    let mut g = group::Group::<Grid>::from_shape(10, 4, 5);
+    g.set_memo_bound(Some(MemoBound {
+        capacity: Some(20),
+        byte_budget: None,
+        eviction: Eviction::Lru,
+    }));
+    g.advance_epoch(20, "rand", 1, 0.5, 0.5);
+    let tracked_bytes = |g: &group::Group<Grid>| -> usize {
+        g.memo_as_ref()
+            .iter()
+            .map(|(key, value)| MemoCache::entry_bytes(key, value))
+            .sum()
+    };
+    assert_eq!(g.memo_cache_as_ref().bytes(), tracked_bytes(&g));
+
+    g.set_memo_keying(MemoKeying::Hash128);
+    assert_eq!(g.memo_cache_as_ref().bytes(), tracked_bytes(&g));
+    g.advance_epoch(20, "rand", 1, 0.5, 0.5);
+    assert!(g.memo_as_ref().len() <= 20);
+    assert_eq!(g.memo_cache_as_ref().bytes(), tracked_bytes(&g));
+
+    let best = g.get_best().1.features.clone();
+    let key = g.memo_key(&best);
+    assert!(g.remove_memo(&key).is_some());
+    assert_eq!(g.memo_cache_as_ref().bytes(), tracked_bytes(&g));
+
+    g.clear_memo();
+    assert!(g.memo_as_ref().is_empty());
+    assert_eq!(g.memo_cache_as_ref().bytes(), 0);
+    assert!(g.memo_cache_as_ref().bound().is_some());
+}
+
+#[test]
+fn memo_queries() {
+    let mut g = group::Group::<Grid>::from_shape(10, 4, 7);
+    g.advance_epoch(30, "rand", 1, 0.5, 0.5);
+
+    let sorted = g.get_sorted_memo();
+    let top = g.top_memo(5);
+    assert_eq!(top.len(), 5);
+    assert_eq!(
+        top.iter().map(|x| &x.1).collect::<Vec<_>>(),
+        sorted[..5].iter().map(|x| &x.1).collect::<Vec<_>>()
+    );
+    assert_eq!(g.top_memo(usize::MAX).len(), g.memo_as_ref().len());
+
+    let near_zero = g.filter_memo(|features, _| features.iter().all(|f| f.abs() <= 1));
+    assert!(!near_zero.is_empty());
+    for (features, evaluations) in near_zero.iter() {
+        assert_eq!(g.get_memo(features), Some(evaluations));
+    }
+    assert_eq!(g.get_memo(&[100, 100, 100, 100]), None);
+
+    // single objective: the pareto subset is the set of best entries
+    let pareto = g.pareto_memo();
+    assert!(pareto.iter().all(|x| x.1 == top[0].1));
+}
+
+#[test]
+fn merge_memos_with_conflicts() {
+    let mut a = group::Group::<Grid>::from_shape(10, 4, 1);
+    a.advance_epoch(10, "rand", 1, 0.5, 0.5);
+    let mut b = group::Group::<Grid>::from_shape(10, 4, 2);
+    b.advance_epoch(10, "rand", 1, 0.5, 0.5);
+
+    let csv = temp_path("merge.csv");
+    b.save_memo_to_csv(&csv);
+
+    let mut pooled = group::Group::<Grid>::new();
+    let report = pooled.merge_memo_files(&[&csv], MergePolicy::Fail).unwrap();
+    assert_eq!(report.added, b.memo_as_ref().len());
+    let report = pooled
+        .merge_memos(&[a.memo_as_ref()], MergePolicy::Fail)
+        .unwrap();
+    assert!(report.conflicts.is_empty());
+    assert!(pooled.memo_as_ref().len() >= a.memo_as_ref().len());
+    remove_file(&csv).unwrap();
+
+    // a changed evaluator
+    let (key, value) = a.top_memo(1).remove(0);
+    let mut noisy = a.memo_as_ref().clone();
+    noisy.insert(key.clone(), vec![value[0] - 2.0]);
+
+    let before = pooled.memo_as_ref().clone();
+    let Err(MergeError::Conflict(conflict)) = pooled.merge_memos(&[&noisy], MergePolicy::Fail)
+    else {
+        panic!("the conflict was not reported");
+    };
+    assert_eq!(conflict.key, key.to_string());
+    assert_eq!(pooled.memo_as_ref(), &before);
+
+    let report = pooled
+        .merge_memos(&[&noisy], MergePolicy::KeepFirst)
+        .unwrap();
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(pooled.memo_as_ref()[&key], value);
+
+    // only the merged memos are averaged, so merging again does not drift
+    for _ in 0..2 {
+        pooled
+            .merge_memos(&[a.memo_as_ref(), &noisy], MergePolicy::Average)
+            .unwrap();
+        assert_eq!(pooled.memo_as_ref()[&key], vec![value[0] - 1.0]);
+    }
+
+    let mut longer = noisy.clone();
+    longer.insert(key.clone(), vec![value[0], 0.0]);
+    let report = pooled
+        .merge_memos(&[&noisy, &longer], MergePolicy::Average)
+        .unwrap();
+    assert_eq!(report.unmerged.len(), 1);
+    assert_eq!(report.unmerged[0].key, key.to_string());
+    assert_eq!(pooled.memo_as_ref()[&key], vec![value[0] - 1.0]);
+
+    let report = pooled
+        .merge_memos(&[&noisy], MergePolicy::KeepLatest)
+        .unwrap();
+    assert_eq!(report.updated, 1);
+    assert_eq!(pooled.memo_as_ref()[&key], vec![value[0] - 2.0]);
+}
+
+#[test]
+fn fingerprint_mismatch_is_refused() {
+    let csv = temp_path("fingerprint.csv");
+    let json = temp_path("fingerprint.json");
+    let log = temp_path("fingerprint.log");
+    let _ = remove_file(&log);
+
+    let mut g = group::Group::<Grid>::from_shape(10, 4, 0);
+    g.set_fingerprint(Some("grid-v1".to_string()));
+    g.open_memo_log(&log).unwrap();
+    g.advance_epoch(5, "rand", 1, 0.5, 0.5);
+    g.close_memo_log();
+    g.save_memo_to_csv(&csv);
+    g.save_to_json(&json);
+
+    let mut same = group::Group::<Grid>::new();
+    same.set_fingerprint(Some("grid-v1".to_string()));
+    same.load_memo_from_csv(&csv);
+    assert_eq!(same.memo_as_ref(), g.memo_as_ref());
+    same.open_memo_log(&log).unwrap();
+    same.close_memo_log();
+
+    let mut changed = group::Group::<Grid>::new();
+    changed.set_fingerprint(Some("grid-v2".to_string()));
+    let mismatch = changed.try_load_memo_from_csv(&csv).unwrap_err();
+    assert_eq!(mismatch.found.as_deref(), Some("grid-v1"));
+    assert!(changed.memo_as_ref().is_empty());
+
+    // without a fingerprint anything is accepted, including old memo files
+    let mut unversioned = group::Group::<Grid>::new();
+    unversioned.load_memo_from_csv(&csv);
+    assert_eq!(unversioned.memo_as_ref(), g.memo_as_ref());
+
+    let checkpoint = group::Group::<Grid>::load_from_json_with_fingerprint(&json, 0, "grid-v1");
+    assert_eq!(checkpoint.unwrap().get_fingerprint(), Some("grid-v1"));
+    assert!(group::Group::<Grid>::load_from_json_with_fingerprint(&json, 0, "grid-v2").is_err());
+
+    remove_file(&csv).unwrap();
+    remove_file(&json).unwrap();
+    remove_file(&log).unwrap();
+}
+
+#[test]
+fn fingerprint_mismatch_on_memo_log() {
+    let log = temp_path("fingerprint_mismatch.log");
+    let _ = remove_file(&log);
+    let mut g = group::Group::<Grid>::from_shape(10, 4, 0);
+    g.set_fingerprint(Some("grid-v1".to_string()));
+    g.open_memo_log(&log).unwrap();
+    g.advance_epoch(1, "rand", 1, 0.5, 0.5);
+    g.close_memo_log();
+
+    let before = read_to_string(&log).unwrap();
+    let mut changed = group::Group::<Grid>::new();
+    changed.set_fingerprint(Some("grid-v2".to_string()));
+    let mismatch = changed.open_memo_log(&log).unwrap_err();
+    assert_eq!(mismatch.found.as_deref(), Some("grid-v1"));
+    assert!(changed.memo_as_ref().is_empty());
+    assert_eq!(read_to_string(&log).unwrap(), before);
+
+    let Err(MergeError::Fingerprint(mismatch)) =
+        changed.merge_memo_files(&[&log], MergePolicy::KeepFirst)
+    else {
+        panic!("the memo file was merged");
+    };
+    assert_eq!(mismatch.expected, "grid-v2");
+    assert!(matches!(
+        changed.merge_group_memos(&[&g], MergePolicy::KeepFirst),
+        Err(MergeError::Fingerprint(_))
+    ));
+    assert!(changed.memo_as_ref().is_empty());
+
+    let mut same = group::Group::<Grid>::new();
+    same.set_fingerprint(Some("grid-v1".to_string()));
+    let report = same
+        .merge_group_memos(&[&g], MergePolicy::KeepFirst)
+        .unwrap();
+    assert_eq!(report.added, g.memo_as_ref().len());
+    remove_file(&log).unwrap();
+}
+
+#[test]
+fn seed_population_from_memo() {
+    let mut previous = group::Group::<Grid>::from_shape(10, 4, 0);
+    previous.advance_epoch(50, "rand", 1, 0.5, 0.5);
+    let best = previous.get_best().1.evals.clone();
+
+    let mut g = group::Group::<Grid>::from_shape(10, 4, 1);
+    g.merge_memos(&[previous.memo_as_ref()], MergePolicy::Fail)
+        .unwrap();
+    let seeded = g.seed_from_memo(3);
+    assert_eq!(seeded, 3);
+
+    let misses = g.get_memo_stats().misses;
+    g.advance_epoch(0, "rand", 1, 0.5, 0.5);
+    assert_eq!(g.get_best().1.evals, best);
+    // the seeds map back to their memo entries
+    assert_eq!(g.get_memo_stats().misses - misses, 7);
+
+    let hand_designed = <Grid as individual::ExtMinimum>::from_features(&[0, 1, -1, 0]).unwrap();
+    assert_eq!(hand_designed.features, vec![0, 1, -1, 0]);
+
+    let seeds = vec![vec![0, 1, -1, 0], vec![2, 2, 2, 2]];
+    let g = group::Group::<Grid>::from_feature_seeds(10, 4, &seeds, 1);
+    assert_eq!(g.get_individuals().len(), 10);
+    assert_eq!(
+        individual::Minimum::identificate(&g.get_individuals()[0]),
+        seeds[0]
+    );
+    assert_eq!(
+        individual::Minimum::identificate(&g.get_individuals()[1]),
+        seeds[1]
+    );
+}
+
+#[test]
+fn batch_evaluation_after_memo_filtering() {
+    let mut single = group::Group::<Grid>::from_shape(10, 4, 4);
+    single.advance_epoch(30, "rand", 1, 0.5, 0.5);
+
+    let batches = Arc::new(Mutex::new(vec![]));
+    let recorded = batches.clone();
+    let mut batched = group::Group::<Grid>::from_shape(10, 4, 4);
+    batched.set_batch_evaluator(Some(Box::new(move |individuals: &[Grid]| {
+        recorded.lock().unwrap().push(individuals.len());
+        individuals
+            .iter()
+            .map(|i| vec![i.features.iter().fold(0.0, |a, b| a - (b * b) as f64)])
+            .collect::<Vec<Vec<f64>>>()
+    })));
+    batched.advance_epoch(30, "rand", 1, 0.5, 0.5);
+
+    assert_eq!(
+        single
+            .get_individuals()
+            .iter()
+            .map(|i| &i.genes)
+            .collect::<Vec<_>>(),
+        batched
+            .get_individuals()
+            .iter()
+            .map(|i| &i.genes)
+            .collect::<Vec<_>>()
+    );
+    let batches = batches.lock().unwrap();
+    // one call per generation at most, with the memo misses only
+    assert!(batches.len() <= 31);
+    assert!(batches.iter().all(|len| *len <= 10));
+    assert_eq!(batches.iter().sum::<usize>(), batched.memo_as_ref().len());
+    assert_eq!(batched.get_memo_stats().misses, batched.memo_as_ref().len());
+}
@@ -0,0 +1,246 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::memo::{self, ExtMemoLog};
+use ys_differential_evolution::method;
+use ys_differential_evolution::method::ExtMemoizationDE;
+use ys_differential_evolution::noise::*;
+
+const NOISE: f64 = 10.0;
+
+fn truth(features: &[i64]) -> f64 {
+    features.iter().fold(0.0, |a, b| a - (b * b) as f64)
+}
+
+/// -sum(features^2) plus gaussian noise
+fn noisy(features: &[i64]) -> Vec<f64> {
+    let mut rng = rand::thread_rng();
+    let (u, v): (f64, f64) = (rng.gen_range(f64::EPSILON..1.0), rng.gen());
+    let gaussian = (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos();
+    vec![truth(features) + NOISE * gaussian]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Grid {
+    genes: Vec<f64>,
+    features: Vec<i64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Grid {
+    type Feature = i64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes
+            .iter()
+            .map(|x| ((*x - 0.5) * 20.0).round() as i64)
+            .collect()
+    }
+
+    fn evaluate(&self) -> Vec<f64> {
+        noisy(&self.features)
+    }
+}
+
+fn noise() -> Noise {
+    Noise {
+        samples: 3,
+        reevaluation_interval: 5,
+        reevaluations: 2,
+        z: 1.96,
+    }
+}
+
+/// (reported - true value) of the best individual
+fn optimism(g: &group::Group<Grid>) -> f64 {
+    let best = g.get_best().1;
+    best.evals[0] - truth(&best.features)
+}
+
+#[test]
+fn noisy_runs_average_samples() {
+    let (mut plain_optimism, mut noisy_optimism) = (0.0, 0.0);
+    for seed in 0..4 {
+        let mut plain = group::Group::<Grid>::from_shape(20, 4, seed);
+        plain.advance_epoch(40, "rand", 1, 0.5, 0.5);
+        plain_optimism += optimism(&plain);
+
+        let mut noisy = group::Group::<Grid>::from_shape(20, 4, seed);
+        noisy.options_as_mut().noise = Some(noise());
+        noisy.advance_epoch(40, "rand", 1, 0.5, 0.5);
+        noisy_optimism += optimism(&noisy);
+
+        // memoは全sampleの平均
+        for (key, value) in noisy.memo_as_ref().iter() {
+            let samples = &noisy.samples_as_ref()[key];
+            assert!(samples.count >= 3);
+            assert_eq!(*value, samples.mean);
+        }
+        let (_, best, estimate) = noisy.get_best_with_confidence();
+        assert_eq!(estimate.mean, best.evals);
+        assert!(estimate.count >= 3);
+        let (lower, upper) = estimate.interval[0];
+        assert!(lower < estimate.mean[0] && estimate.mean[0] < upper);
+    }
+    // 1回の幸運な評価が残らない
+    assert!(noisy_optimism < plain_optimism);
+}
+
+#[test]
+fn population_is_reevaluated_on_schedule() {
+    let mut g = group::Group::<Grid>::from_shape(20, 4, 0);
+    g.options_as_mut().noise = Some(noise());
+    g.advance_epoch(5, "rand", 1, 0.5, 0.5);
+    for individual in g.get_individuals() {
+        let key = g.memo_key(&individual.features);
+        assert!(g.samples_as_ref()[&key].count >= 5);
+        assert_eq!(individual.evals, g.samples_as_ref()[&key].mean);
+    }
+}
+
+#[test]
+fn samples_survive_a_checkpoint() {
+    let mut g = group::Group::<Grid>::from_shape(20, 4, 0);
+    g.options_as_mut().noise = Some(noise());
+    g.advance_epoch(5, "rand", 1, 0.5, 0.5);
+
+    let json = std::env::temp_dir().join(format!("ys_de_{}_noise.json", std::process::id()));
+    let json = json.to_str().unwrap();
+    g.save_to_json(json);
+    let loaded = group::Group::<Grid>::load_from_json(json, 0);
+    std::fs::remove_file(json).unwrap();
+    assert!(!loaded.samples_as_ref().is_empty());
+    assert_eq!(loaded.samples_as_ref(), g.samples_as_ref());
+    for (key, samples) in g.samples_as_ref().iter() {
+        assert_eq!(loaded.samples_as_ref()[key].variance(), samples.variance());
+    }
+}
+
+#[test]
+#[should_panic(expected = "Noise::samples must be at least 2")]
+fn a_single_sample_is_rejected() {
+    let mut g = group::Group::<Grid>::from_shape(20, 4, 0);
+    g.options_as_mut().noise = Some(Noise {
+        samples: 1,
+        ..noise()
+    });
+    g.advance_epoch(1, "rand", 1, 0.5, 0.5);
+}
+
+#[test]
+fn selection_needs_a_significant_difference() {
+    let mut samples = Samples::default();
+    for x in [1.0, 2.0, 3.0, 4.0] {
+        samples.add(&[x]);
+    }
+    assert_eq!(samples.mean, vec![2.5]);
+    assert!((samples.variance()[0] - 5.0 / 3.0).abs() < 1e-12);
+
+    let mut g = group::Group::<Grid>::from_shape(4, 2, 0);
+    g.options_as_mut().noise = Some(noise());
+    let individual = |features: Vec<i64>, evals: Vec<f64>| Grid {
+        genes: vec![0.5, 0.5],
+        features,
+        evals,
+    };
+    let incumbent = individual(vec![0, 0], vec![2.5]);
+    let close = individual(vec![0, 1], vec![3.5]);
+    let far = individual(vec![1, 1], vec![10.0]);
+    let key = g.memo_key(&[0, 0]);
+    g.samples_as_mut().insert(key, samples.clone());
+    let mut shifted = Samples::default();
+    for x in [2.0, 3.0, 4.0, 5.0] {
+        shifted.add(&[x]);
+    }
+    let key = g.memo_key(&[0, 1]);
+    g.samples_as_mut().insert(key, shifted);
+    let mut distant = Samples::default();
+    for x in [9.0, 10.0, 11.0] {
+        distant.add(&[x]);
+    }
+    let key = g.memo_key(&[1, 1]);
+    g.samples_as_mut().insert(key, distant);
+
+    assert!(!g.wins(&incumbent, &close));
+    assert!(g.wins(&incumbent, &far));
+    assert!(!g.wins(&far, &incumbent));
+    g.options_as_mut().noise = None;
+    assert!(g.wins(&incumbent, &close));
+}
+
+#[test]
+fn reevaluation_keeps_one_log_line_per_key() {
+    let log = std::env::temp_dir().join(format!("ys_de_{}_noise.log", std::process::id()));
+    let log = log.to_str().unwrap();
+    let _ = std::fs::remove_file(log);
+    let mut g = group::Group::<Grid>::from_shape(20, 4, 0);
+    g.options_as_mut().noise = Some(noise());
+    g.open_memo_log(log).unwrap();
+    g.advance_epoch(20, "rand", 1, 0.5, 0.5);
+    g.close_memo_log();
+
+    let written = memo::read_memo_file(log);
+    let lines = std::fs::read_to_string(log).unwrap().lines().count();
+    std::fs::remove_file(log).unwrap();
+    assert_eq!(lines, written.len());
+    assert_eq!(written.len(), g.memo_as_ref().len());
+    for (key, value) in g.memo_as_ref().iter() {
+        assert_eq!(&written[&key.to_string()], value);
+    }
+}
+
+#[test]
+fn confidence_needs_two_samples() {
+    let mut g = group::Group::<Grid>::from_shape(20, 4, 0);
+    g.advance_epoch(1, "rand", 1, 0.5, 0.5);
+    // without noise the evaluation is exact
+    let (_, best, estimate) = g.get_best_with_confidence();
+    assert_eq!(estimate.interval[0], (best.evals[0], best.evals[0]));
+
+    g.options_as_mut().noise = Some(noise());
+    let (_, _, estimate) = g.get_best_with_confidence();
+    assert_eq!(estimate.count, 1);
+    assert_eq!(estimate.interval[0], (f64::NEG_INFINITY, f64::INFINITY));
+}
+
+#[test]
+#[should_panic(expected = "Options::noise needs the memo")]
+fn noise_is_refused_without_a_memo() {
+    let mut g = group::Group::<Grid>::from_shape(20, 4, 0);
+    g.options_as_mut().noise = Some(noise());
+    method::ExtConfiguredDE::advance_epoch(&mut g, 1, "rand", 1, 0.5, 0.5);
+}
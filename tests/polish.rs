@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::method::{ExtMemoizationDE, Memetic, WriteBack};
+use ys_differential_evolution::polish::*;
+use ys_differential_evolution::robust::{aggregate, Aggregation, Robustness};
+
+/// smooth, optimum at genes = 0.3, 0.4, ...
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Bowl {
+    genes: Vec<f64>,
+    features: Vec<f64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Bowl {
+    type Feature = f64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes.clone()
+    }
+
+    fn evaluate(&self) -> Vec<f64> {
+        let sum = self.features.iter().enumerate().fold(0.0, |a, (i, x)| {
+            let d = x - 0.3 - 0.1 * i as f64;
+            a + (i + 1) as f64 * d * d
+        });
+        vec![-sum]
+    }
+}
+
+fn polished(method: Polish, max_evaluations: usize) -> (f64, f64, PolishReport) {
+    let mut g = group::Group::<Bowl>::from_shape(10, 4, 0);
+    g.advance_epoch(20, "rand", 1, 0.5, 0.9);
+    let before = g.get_best().1.evals[0];
+    let misses = g.get_memo_stats().misses;
+    let report = g.polish(method, max_evaluations);
+    assert_eq!(g.get_memo_stats().misses - misses, report.evaluations);
+    assert!(report.evaluations <= max_evaluations);
+    assert_eq!(report.before, vec![before]);
+    assert_eq!(g.get_best().1.evals, report.after);
+    (before, report.after[0], report)
+}
+
+#[test]
+fn nelder_mead_polish() {
+    let (before, after, report) = polished(Polish::NelderMead { step: 0.05 }, 300);
+    assert!(report.improved);
+    assert!(after > before);
+    assert!(after > -1e-6);
+
+    let (_, _, report) = polished(Polish::NelderMead { step: 0.05 }, 10);
+    assert_eq!(report.evaluations, 10);
+}
+
+#[test]
+fn lbfgsb_polish() {
+    let (before, after, report) = polished(
+        Polish::Lbfgsb {
+            step: 1e-7,
+            memory: 5,
+        },
+        200,
+    );
+    assert!(report.improved);
+    assert!(after > before);
+    assert!(after > -1e-9);
+}
+
+#[test]
+fn polish_stays_in_bounds() {
+    // the optimum of the last gene (0.3 + 0.1 * 9 = 1.2) is outside the bounds
+    let mut g = group::Group::<Bowl>::from_shape(20, 10, 1);
+    g.advance_epoch(30, "rand", 1, 0.5, 0.9);
+    for method in [
+        Polish::NelderMead { step: 0.05 },
+        Polish::Lbfgsb {
+            step: 1e-7,
+            memory: 5,
+        },
+    ] {
+        g.polish(method, 2000);
+        let best = g.get_best().1;
+        assert!(best.genes.iter().all(|x| (0.0..=1.0).contains(x)));
+    }
+    let best = g.get_best().1;
+    assert!(best.genes[9] > 0.999);
+}
+
+#[test]
+fn polish_evaluates_robustly() {
+    let robustness = Robustness::new(4, vec![0.05], Aggregation::Worst, 0);
+    let mut g = group::Group::<Bowl>::from_shape(10, 4, 0);
+    g.options_as_mut().robustness = Some(robustness.clone());
+    g.advance_epoch(20, "rand", 1, 0.5, 0.9);
+    let misses = g.get_memo_stats().misses;
+    let report = g.polish(Polish::Pattern { step: 0.05 }, 200);
+    assert_eq!(g.get_memo_stats().misses - misses, report.evaluations);
+    assert!(report.evaluations <= 200);
+    assert!(report.improved);
+
+    // the polished evaluations are the aggregate of the perturbed copies
+    let best = g.get_best().1.clone();
+    assert_eq!(best.evals, report.after);
+    let copies: Vec<Vec<f64>> = robustness
+        .perturb(&best.genes)
+        .into_iter()
+        .map(|genes| {
+            let copy = <Bowl as individual::ExtMinimum>::from_genes(genes);
+            let features = individual::Minimum::identificate(&copy);
+            g.memo_as_ref()[&g.memo_key(&features)].clone()
+        })
+        .collect();
+    assert_eq!(best.evals, aggregate(&copies, Aggregation::Worst));
+}
+
+fn memetic_run(write_back: Option<WriteBack>) -> group::Group<Bowl> {
+    let mut g = group::Group::<Bowl>::from_shape(10, 4, 2);
+    g.options_as_mut().memetic = write_back.map(|write_back| Memetic {
+        interval: 5,
+        top: 2,
+        search: Polish::Pattern { step: 0.05 },
+        max_evaluations: 30,
+        write_back,
+    });
+    g.advance_epoch(20, "rand", 1, 0.5, 0.9);
+    g
+}
+
+#[test]
+fn memetic_local_search() {
+    let plain = memetic_run(None);
+    let lamarckian = memetic_run(Some(WriteBack::Lamarckian));
+    let baldwinian = memetic_run(Some(WriteBack::Baldwinian));
+
+    let spent = lamarckian.history_as_ref().local_search_evaluations;
+    assert!(spent > 0 && spent <= 4 * 2 * 30);
+    assert_eq!(plain.history_as_ref().local_search_evaluations, 0);
+    assert!(lamarckian.get_best().1.evals > plain.get_best().1.evals);
+
+    // Lamarckian individuals carry their own evaluations, Baldwinian ones the improved ones
+    let consistent = |i: &Bowl| i.evals == individual::Minimum::evaluate(i);
+    assert!(lamarckian.get_individuals().iter().all(consistent));
+    assert!(!baldwinian.get_individuals().iter().all(consistent));
+}
@@ -0,0 +1,195 @@
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use ys_differential_evolution::evaluator::{BatchEvaluator, ExtBatchEvaluation};
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::method::ExtMemoizationDE;
+use ys_differential_evolution::process::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Grid {
+    genes: Vec<f64>,
+    features: Vec<i64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Grid {
+    type Feature = i64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes
+            .iter()
+            .map(|x| ((*x - 0.5) * 20.0).round() as i64)
+            .collect()
+    }
+
+    fn evaluate(&self) -> Vec<f64> {
+        vec![self.features.iter().fold(0.0, |a, b| a - (b * b) as f64)]
+    }
+}
+
+/// The pool runs this test binary again with `YS_DE_WORKER` set, and only this test.
+fn pool(workers: usize, envs: &[(&str, &str)]) -> ProcessPool {
+    let exe = std::env::current_exe().unwrap();
+    let mut pool = ProcessPool::new(
+        exe.to_str().unwrap(),
+        &["worker", "--exact", "--nocapture", "--test-threads=1"],
+        workers,
+    );
+    pool.envs
+        .push(("YS_DE_WORKER".to_string(), "1".to_string()));
+    for (key, value) in envs {
+        pool.envs.push((key.to_string(), value.to_string()));
+    }
+    pool
+}
+
+/// A worker: -sum(features^2). `YS_DE_CRASH_AFTER=n` exits after n evaluations,
+/// `YS_DE_HANG_AT=n` never answers the n-th request (1-based).
+#[test]
+fn worker() {
+    if std::env::var("YS_DE_WORKER").is_err() {
+        return;
+    }
+    let env = |key: &str| std::env::var(key).ok().map(|v| v.parse::<usize>().unwrap());
+    let crash_after = env("YS_DE_CRASH_AFTER");
+    let hang_at = env("YS_DE_HANG_AT");
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    for (n, line) in stdin.lock().lines().enumerate() {
+        if crash_after == Some(n) {
+            std::process::exit(1);
+        }
+        if hang_at == Some(n + 1) {
+            std::thread::sleep(Duration::from_secs(60));
+        }
+        let request: Value = serde_json::from_str(&line.unwrap()).unwrap();
+        let sum = request["features"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .fold(0.0, |a, f| a - f.as_f64().unwrap().powi(2));
+        writeln!(stdout, "log lines are ignored").unwrap();
+        writeln!(
+            stdout,
+            "{{\"id\": {}, \"evaluations\": [{}]}}",
+            request["id"], sum
+        )
+        .unwrap();
+        stdout.flush().unwrap();
+    }
+}
+
+fn genes(g: &group::Group<Grid>) -> Vec<Vec<f64>> {
+    g.get_individuals()
+        .iter()
+        .map(|i| i.genes.clone())
+        .collect()
+}
+
+#[test]
+fn process_pool_matches_in_process_evaluation() {
+    let mut local = group::Group::<Grid>::from_shape(10, 4, 0);
+    local.advance_epoch(10, "rand", 1, 0.5, 0.5);
+
+    let mut remote = group::Group::<Grid>::from_shape(10, 4, 0);
+    remote.set_batch_evaluator(Some(Box::new(pool(3, &[]))));
+    remote.advance_epoch(10, "rand", 1, 0.5, 0.5);
+    assert_eq!(genes(&local), genes(&remote));
+    assert_eq!(local.memo_as_ref(), remote.memo_as_ref());
+}
+
+/// evaluates the initial individuals of a group directly
+fn evaluate_directly(pool: &mut ProcessPool) -> PoolStats {
+    let g = group::Group::<Grid>::from_shape(10, 4, 1);
+    let individuals: Vec<Grid> = g
+        .get_individuals()
+        .iter()
+        .map(|i| {
+            let mut i = i.clone();
+            i.features = individual::Minimum::identificate(&i);
+            i
+        })
+        .collect();
+    let evaluations = BatchEvaluator::evaluate_batch(pool, &individuals);
+    for (i, evaluation) in individuals.iter().zip(evaluations) {
+        assert_eq!(evaluation, individual::Minimum::evaluate(i));
+    }
+    pool.stats()
+}
+
+#[test]
+fn process_pool_restarts_crashed_and_hung_workers() {
+    let stats = evaluate_directly(&mut pool(2, &[("YS_DE_CRASH_AFTER", "4")]));
+    assert_eq!(stats.evaluations, 10);
+    assert!(stats.crashes >= 1);
+    // a worker is restarted only when it gets another request
+    assert!(stats.spawns > 2 && stats.spawns <= 2 + stats.crashes);
+
+    let mut hanging = pool(2, &[("YS_DE_HANG_AT", "4")]);
+    hanging.timeout = Duration::from_millis(300);
+    let stats = evaluate_directly(&mut hanging);
+    assert!(stats.timeouts >= 1);
+    assert!(stats.spawns > 2 && stats.spawns <= 2 + stats.timeouts);
+
+    let mut local = group::Group::<Grid>::from_shape(10, 4, 1);
+    local.advance_epoch(2, "rand", 1, 0.5, 0.5);
+    let mut g = group::Group::<Grid>::from_shape(10, 4, 1);
+    g.set_batch_evaluator(Some(Box::new(hanging)));
+    g.advance_epoch(2, "rand", 1, 0.5, 0.5);
+    assert_eq!(genes(&local), genes(&g));
+    assert_eq!(local.memo_as_ref(), g.memo_as_ref());
+}
+
+#[test]
+fn non_finite_evaluations_are_written_as_strings() {
+    let line = format_response(3, &[1.5, f64::NAN, f64::INFINITY, f64::NEG_INFINITY]);
+    assert_eq!(line, r#"{"evaluations":[1.5,"NaN","inf","-inf"],"id":3}"#);
+    let (id, evaluations) = parse_response(&line).unwrap();
+    assert_eq!(id, 3);
+    assert_eq!(evaluations[0], 1.5);
+    assert!(evaluations[1].is_nan());
+    assert_eq!(evaluations[2..], [f64::INFINITY, f64::NEG_INFINITY]);
+
+    // what serde_json and JSON.stringify write for NaN
+    let (_, evaluations) = parse_response(r#"{"id": 0, "evaluations": [null, 2]}"#).unwrap();
+    assert!(evaluations[0].is_nan());
+    assert_eq!(evaluations[1], 2.0);
+    assert!(parse_response(r#"{"id": 0, "evaluations": ["high"]}"#).is_none());
+}
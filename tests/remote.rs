@@ -0,0 +1,252 @@
+use std::net::SocketAddr;
+use std::process::{Child, Command};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use ys_differential_evolution::evaluator::{BatchEvaluator, ExtBatchEvaluation};
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::method::ExtMemoizationDE;
+use ys_differential_evolution::remote::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Grid {
+    genes: Vec<f64>,
+    features: Vec<i64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Grid {
+    type Feature = i64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes
+            .iter()
+            .map(|x| ((*x - 0.5) * 20.0).round() as i64)
+            .collect()
+    }
+
+    fn evaluate(&self) -> Vec<f64> {
+        vec![self.features.iter().fold(0.0, |a, b| a - (b * b) as f64)]
+    }
+}
+
+/// A worker process: this test binary again, running only `worker`.
+/// `YS_DE_DISCONNECT_AFTER=n` drops the connection when the (n + 1)-th request arrives.
+fn spawn_worker(coordinator: SocketAddr, disconnect_after: Option<usize>) -> Child {
+    let mut command = Command::new(std::env::current_exe().unwrap());
+    command
+        .args(["worker", "--exact", "--nocapture", "--test-threads=1"])
+        .env("YS_DE_COORDINATOR", coordinator.to_string());
+    if let Some(n) = disconnect_after {
+        command.env("YS_DE_DISCONNECT_AFTER", n.to_string());
+    }
+    command.spawn().unwrap()
+}
+
+#[test]
+fn worker() {
+    let Ok(addr) = std::env::var("YS_DE_COORDINATOR") else {
+        return;
+    };
+    match std::env::var("YS_DE_DISCONNECT_AFTER") {
+        Ok(n) => {
+            let n: usize = n.parse().unwrap();
+            let mut count = 0;
+            serve(addr, |genes, _| {
+                if count == n {
+                    std::process::exit(0);
+                }
+                count += 1;
+                let mut grid = <Grid as individual::ExtMinimum>::from_genes(genes.to_vec());
+                grid.features = individual::Minimum::identificate(&grid);
+                individual::Minimum::evaluate(&grid)
+            })
+            .unwrap();
+        }
+        Err(_) => {
+            run_worker::<Grid, _>(addr).unwrap();
+        }
+    }
+}
+
+#[test]
+fn coordinator_with_worker_processes() {
+    let mut local = group::Group::<Grid>::from_shape(10, 4, 0);
+    local.advance_epoch(10, "rand", 1, 0.5, 0.5);
+
+    let mut coordinator = Coordinator::bind("127.0.0.1:0").unwrap();
+    coordinator.connect_timeout = Duration::from_secs(30);
+    let mut workers = [
+        spawn_worker(coordinator.local_addr(), None),
+        spawn_worker(coordinator.local_addr(), None),
+        spawn_worker(coordinator.local_addr(), Some(5)),
+    ];
+    assert!(coordinator.wait_for_workers(3));
+
+    let mut remote = group::Group::<Grid>::from_shape(10, 4, 0);
+    remote.set_batch_evaluator(Some(Box::new(coordinator)));
+    remote.advance_epoch(10, "rand", 1, 0.5, 0.5);
+    assert_eq!(
+        local
+            .get_individuals()
+            .iter()
+            .map(|i| &i.genes)
+            .collect::<Vec<_>>(),
+        remote
+            .get_individuals()
+            .iter()
+            .map(|i| &i.genes)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(local.memo_as_ref(), remote.memo_as_ref());
+
+    // closing the coordinator stops the workers
+    remote.set_batch_evaluator(None);
+    for worker in workers.iter_mut() {
+        assert!(worker.wait().unwrap().success());
+    }
+}
+
+#[test]
+fn disconnected_work_is_requeued() {
+    let mut coordinator = Coordinator::bind("127.0.0.1:0").unwrap();
+    coordinator.connect_timeout = Duration::from_secs(30);
+    let mut flaky = spawn_worker(coordinator.local_addr(), Some(2));
+    assert!(coordinator.wait_for_workers(1));
+
+    let g = group::Group::<Grid>::from_shape(20, 4, 1);
+    let individuals: Vec<Grid> = g
+        .get_individuals()
+        .iter()
+        .map(|i| {
+            let mut i = i.clone();
+            i.features = individual::Minimum::identificate(&i);
+            i
+        })
+        .collect();
+    // the steady worker joins only after the flaky one is gone
+    let addr = coordinator.local_addr();
+    let steady = std::thread::spawn(move || {
+        flaky.wait().unwrap();
+        spawn_worker(addr, None)
+    });
+    let evaluations = ys_differential_evolution::evaluator::BatchEvaluator::evaluate_batch(
+        &mut coordinator,
+        &individuals,
+    );
+    for (i, evaluation) in individuals.iter().zip(evaluations) {
+        assert_eq!(evaluation, individual::Minimum::evaluate(i));
+    }
+    let stats = coordinator.stats();
+    assert_eq!(stats.evaluations, 20);
+    assert_eq!(stats.connections, 2);
+    assert_eq!(stats.requeued, 1);
+
+    drop(coordinator);
+    assert!(steady.join().unwrap().wait().unwrap().success());
+}
+
+#[test]
+fn hanging_work_is_requeued() {
+    let mut coordinator = Coordinator::bind("127.0.0.1:0").unwrap();
+    coordinator.connect_timeout = Duration::from_secs(30);
+    coordinator.timeout = Duration::from_millis(200);
+    let addr = coordinator.local_addr();
+    // connected first, so it gets the first request
+    std::thread::spawn(move || {
+        serve(addr, |_, _| {
+            std::thread::sleep(Duration::from_secs(5));
+            vec![]
+        })
+    });
+    assert!(coordinator.wait_for_workers(1));
+    let mut steady = spawn_worker(addr, None);
+    assert!(coordinator.wait_for_workers(2));
+
+    let g = group::Group::<Grid>::from_shape(5, 4, 2);
+    let individuals: Vec<Grid> = g
+        .get_individuals()
+        .iter()
+        .map(|i| {
+            let mut i = i.clone();
+            i.features = individual::Minimum::identificate(&i);
+            i
+        })
+        .collect();
+    let evaluations = ys_differential_evolution::evaluator::BatchEvaluator::evaluate_batch(
+        &mut coordinator,
+        &individuals,
+    );
+    for (i, evaluation) in individuals.iter().zip(evaluations) {
+        assert_eq!(evaluation, individual::Minimum::evaluate(i));
+    }
+    let stats = coordinator.stats();
+    assert_eq!(stats.timeouts, 1);
+    assert_eq!(stats.requeued, 1);
+    assert_eq!(stats.evaluations, 5);
+    assert_eq!(coordinator.worker_count(), 1);
+
+    drop(coordinator);
+    assert!(steady.wait().unwrap().success());
+}
+
+#[test]
+fn non_finite_evaluations_are_not_dropped() {
+    let mut coordinator = Coordinator::bind("127.0.0.1:0").unwrap();
+    coordinator.connect_timeout = Duration::from_secs(30);
+    let addr = coordinator.local_addr();
+    let worker = std::thread::spawn(move || {
+        serve(addr, |_, _| {
+            vec![f64::NAN, f64::INFINITY, f64::NEG_INFINITY]
+        })
+        .unwrap()
+    });
+    assert!(coordinator.wait_for_workers(1));
+
+    let individuals = group::Group::<Grid>::from_shape(3, 4, 0)
+        .get_individuals()
+        .clone();
+    let evaluations = BatchEvaluator::<Grid>::evaluate_batch(&mut coordinator, &individuals);
+    assert_eq!(evaluations.len(), 3);
+    for evaluation in evaluations {
+        assert!(evaluation[0].is_nan());
+        assert_eq!(evaluation[1..], [f64::INFINITY, f64::NEG_INFINITY]);
+    }
+
+    drop(coordinator);
+    assert_eq!(worker.join().unwrap(), 3);
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::method::{self, ExtMemoizationDE};
+use ys_differential_evolution::robust::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Ridge {
+    genes: Vec<f64>,
+    features: Vec<i64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Ridge {
+    type Feature = i64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    /// genes on a grid of 1/1000
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes
+            .iter()
+            .map(|x| (*x * 1000.0).round() as i64)
+            .collect()
+    }
+
+    /// a narrow peak of 1.0 at 0.2 and a broad one of 0.8 at 0.7, for every gene
+    fn evaluate(&self) -> Vec<f64> {
+        let peak = |x: f64, center: f64, width: f64| (-((x - center) / width).powi(2)).exp();
+        let value = self
+            .features
+            .iter()
+            .map(|f| {
+                let x = *f as f64 / 1000.0;
+                (1.0 * peak(x, 0.2, 0.05)).max(0.8 * peak(x, 0.7, 0.2))
+            })
+            .sum();
+        vec![value]
+    }
+
+    fn encode(features: &[Self::Feature]) -> Option<Vec<f64>> {
+        Some(features.iter().map(|f| *f as f64 / 1000.0).collect())
+    }
+}
+
+fn robustness(aggregation: Aggregation) -> Robustness {
+    Robustness::new(8, vec![0.08], aggregation, 0)
+}
+
+fn best_genes(g: &group::Group<Ridge>) -> Vec<f64> {
+    g.get_best().1.genes.clone()
+}
+
+#[test]
+fn robust_optimum_is_the_broad_peak() {
+    let mut plain = group::Group::<Ridge>::from_shape(20, 1, 0);
+    plain.advance_epoch(60, "rand", 1, 0.5, 0.5);
+    assert!(best_genes(&plain).iter().all(|x| (x - 0.2).abs() < 0.05));
+
+    for aggregation in [
+        Aggregation::Mean,
+        Aggregation::Worst,
+        Aggregation::Quantile(0.25),
+    ] {
+        let mut robust = group::Group::<Ridge>::from_shape(20, 1, 0);
+        robust.options_as_mut().robustness = Some(robustness(aggregation));
+        robust.advance_epoch(60, "rand", 1, 0.5, 0.5);
+        assert!(best_genes(&robust).iter().all(|x| (x - 0.7).abs() < 0.1));
+
+        // perturbed copies are memoized, the effective evaluations are not
+        let best = robust.get_best().1.clone();
+        let copies: Vec<Vec<f64>> = robustness(aggregation)
+            .perturb(&best.genes)
+            .into_iter()
+            .map(|genes| {
+                let copy = <Ridge as individual::ExtMinimum>::from_genes(genes);
+                let features = individual::Minimum::identificate(&copy);
+                robust.memo_as_ref()[&robust.memo_key(&features)].clone()
+            })
+            .collect();
+        assert_eq!(best.evals, aggregate(&copies, aggregation));
+        assert!(robust.get_memo_stats().hits > 0);
+    }
+}
+
+#[test]
+fn robustness_without_memo() {
+    let mut g = group::Group::<Ridge>::from_shape(20, 1, 0);
+    g.options_as_mut().robustness = Some(robustness(Aggregation::Mean));
+    method::ExtConfiguredDE::advance_epoch(&mut g, 60, "rand", 1, 0.5, 0.5);
+    assert!(best_genes(&g).iter().all(|x| (x - 0.7).abs() < 0.1));
+    assert!(g.memo_as_ref().is_empty());
+}
+
+#[test]
+fn aggregations() {
+    let evaluations = vec![
+        vec![3.0, 1.0],
+        vec![1.0, 5.0],
+        vec![2.0, 0.0],
+        vec![4.0, 2.0],
+    ];
+    assert_eq!(aggregate(&evaluations, Aggregation::Mean), vec![2.5, 2.0]);
+    assert_eq!(aggregate(&evaluations, Aggregation::Worst), vec![1.0, 5.0]);
+    assert_eq!(
+        aggregate(&evaluations, Aggregation::Quantile(0.0)),
+        vec![1.0, 5.0]
+    );
+    assert_eq!(
+        aggregate(&evaluations, Aggregation::Quantile(1.0)),
+        vec![4.0, 2.0]
+    );
+    assert_eq!(
+        aggregate(&evaluations, Aggregation::Quantile(0.5)),
+        vec![3.0, 1.0]
+    );
+    // a failed copy
+    assert!(aggregate(&[vec![1.0], vec![]], Aggregation::Mean).is_empty());
+    for aggregation in [
+        Aggregation::Mean,
+        Aggregation::Worst,
+        Aggregation::Quantile(0.5),
+    ] {
+        assert!(aggregate(&[vec![1.0], vec![f64::NAN]], aggregation).is_empty());
+    }
+
+    let r = robustness(Aggregation::Mean);
+    let copies = r.perturb(&[0.0, 0.5]);
+    assert_eq!(copies.len(), 8);
+    assert_eq!(copies, r.perturb(&[0.0, 0.5]));
+    for copy in copies {
+        assert!((0.0..=0.08).contains(&copy[0]));
+        assert!((0.42..=0.58).contains(&copy[1]));
+    }
+}
+
+#[test]
+#[should_panic(expected = "Robustness::copies must be at least 1")]
+fn robustness_needs_a_copy() {
+    Robustness::new(0, vec![0.08], Aggregation::Mean, 0);
+}
@@ -0,0 +1,353 @@
+use std::cell::Cell;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use ys_differential_evolution::evaluator::ExtBatchEvaluation;
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::hall_of_fame::ExtHallOfFame;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::memo::ExtMemoQuery;
+use ys_differential_evolution::method;
+use ys_differential_evolution::method::ExtMemoizationDE;
+use ys_differential_evolution::method::{ExtNiching, Niching, Restart, Space, Updating};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Rastrigin {
+    genes: Vec<f64>,
+    features: Vec<f64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Rastrigin {
+    type Feature = f64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    /// genes in [0, 1] -> x in [-5.12, 5.12]
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes.iter().map(|g| (g - 0.5) * 10.24).collect()
+    }
+
+    fn evaluate(&self) -> Vec<f64> {
+        let sum = self.features.iter().fold(0.0, |a, x| {
+            a + x * x - 10.0 * (2.0 * std::f64::consts::PI * x).cos() + 10.0
+        });
+        vec![-sum]
+    }
+}
+
+fn best_after(g: &mut group::Group<Rastrigin>, epoch: usize) -> f64 {
+    g.advance_epoch(epoch, "rand", 1, 0.5, 0.9);
+    g.get_best().1.evals[0]
+}
+
+#[test]
+fn opposition_based_generation_jumping() {
+    let mut plain = group::Group::<Rastrigin>::from_shape(20, 4, 0);
+    let mut jumping = group::Group::<Rastrigin>::from_shape(20, 4, 0);
+    jumping.options_as_mut().jumping_rate = Some(0.3);
+
+    let mut previous = f64::MIN;
+    for _ in 0..10 {
+        let best = best_after(&mut jumping, 10);
+        assert!(best >= previous);
+        previous = best;
+    }
+    best_after(&mut plain, 100);
+    // opposite points are evaluated as well
+    assert!(jumping.get_memo_stats().misses > plain.get_memo_stats().misses);
+    assert_eq!(jumping.get_individuals().len(), 20);
+
+    let mut again = group::Group::<Rastrigin>::from_shape(20, 4, 0);
+    again.options_as_mut().jumping_rate = Some(0.3);
+    assert_eq!(best_after(&mut again, 100), previous);
+}
+
+#[test]
+fn immediate_updating() {
+    let mut deferred = group::Group::<Rastrigin>::from_shape(20, 4, 1);
+    let mut immediate = group::Group::<Rastrigin>::from_shape(20, 4, 1);
+    immediate.options_as_mut().updating = Updating::Immediate;
+
+    let mut previous = f64::MIN;
+    for _ in 0..10 {
+        let best = best_after(&mut immediate, 10);
+        assert!(best >= previous);
+        previous = best;
+    }
+    best_after(&mut deferred, 100);
+
+    let stats = immediate.get_memo_stats();
+    assert_eq!(stats.hits + stats.misses, 20 * 101);
+    assert_ne!(immediate.get_best().1.genes, deferred.get_best().1.genes);
+}
+
+#[test]
+fn immediate_updating_mutates_from_replaced_individuals() {
+    // "best" with f_scale 0: every mutant is the best at that moment, so a trial holds genes
+    // of its parent and of that best only
+    let foreign_genes = |updating: Updating| -> (usize, usize) {
+        let mut g = group::Group::<Rastrigin>::from_shape(20, 4, 2);
+        g.options_as_mut().updating = updating;
+        method::ExtConfiguredDE::advance_epoch(&mut g, 0, "best", 1, 0.0, 0.5);
+        let parents: Vec<Vec<f64>> = g
+            .get_individuals()
+            .iter()
+            .map(|i| i.genes.clone())
+            .collect();
+        let best = g.get_best().1.genes.clone();
+
+        let trials = Arc::new(Mutex::new(vec![]));
+        let recorded = trials.clone();
+        g.set_batch_evaluator(Some(Box::new(move |individuals: &[Rastrigin]| {
+            let mut trials = recorded.lock().unwrap();
+            trials.extend(individuals.iter().map(|i| i.genes.clone()));
+            individuals
+                .iter()
+                .map(individual::Minimum::evaluate)
+                .collect::<Vec<Vec<f64>>>()
+        })));
+        method::ExtConfiguredDE::advance_epoch(&mut g, 1, "best", 1, 0.0, 0.5);
+
+        let trials = trials.lock().unwrap();
+        assert_eq!(trials.len(), 20);
+        let (mut foreign, mut from_earlier_trials) = (0, 0);
+        for (k, trial) in trials.iter().enumerate() {
+            for (j, gene) in trial.iter().enumerate() {
+                if *gene != parents[k][j] && *gene != best[j] {
+                    foreign += 1;
+                    if trials[..k].iter().any(|earlier| earlier[j] == *gene) {
+                        from_earlier_trials += 1;
+                    }
+                }
+            }
+        }
+        (foreign, from_earlier_trials)
+    };
+
+    assert_eq!(foreign_genes(Updating::Deferred), (0, 0));
+    let (foreign, from_earlier_trials) = foreign_genes(Updating::Immediate);
+    assert!(foreign > 0);
+    assert_eq!(foreign, from_earlier_trials);
+}
+
+#[test]
+fn restart_with_increasing_population() {
+    let mut g = group::Group::<Rastrigin>::from_shape(10, 2, 0);
+    g.options_as_mut().restart = Some(Restart {
+        gene_spread: 1e-3,
+        evaluation_spread: 1e-6,
+        population_growth: 2.0,
+        max_population: 40,
+    });
+
+    let mut previous = f64::MIN;
+    for _ in 0..30 {
+        let best = best_after(&mut g, 10);
+        assert!(best >= previous);
+        previous = best;
+    }
+
+    let history = g.history_as_ref().clone();
+    assert_eq!(history.epochs, 300);
+    assert!(history.restarts.len() >= 2);
+    let sizes: Vec<usize> = history.restarts.iter().map(|r| r.population_len).collect();
+    assert_eq!(sizes[..2], [20, 40]);
+    assert!(sizes[2..].iter().all(|len| *len == 40));
+    for record in history.restarts.iter() {
+        assert!(record.gene_spread < 1e-3 || record.evaluation_spread < 1e-6);
+    }
+
+    let json = std::env::temp_dir().join(format!("ys_de_{}_restart.json", std::process::id()));
+    let json = json.to_str().unwrap();
+    g.save_to_json(json);
+    let loaded = group::Group::<Rastrigin>::load_from_json(json, 0);
+    assert_eq!(loaded.history_as_ref().epochs, 300);
+    assert_eq!(
+        loaded.history_as_ref().restarts.len(),
+        history.restarts.len()
+    );
+    std::fs::remove_file(json).unwrap();
+}
+
+#[test]
+fn hall_of_fame_survives_restarts() {
+    let mut g = group::Group::<Rastrigin>::from_shape(10, 2, 0);
+    g.set_hall_of_fame_capacity(5);
+    g.options_as_mut().restart = Some(Restart {
+        gene_spread: 1e-3,
+        evaluation_spread: 1e-6,
+        population_growth: 1.0,
+        max_population: 10,
+    });
+    best_after(&mut g, 200);
+    assert!(!g.history_as_ref().restarts.is_empty());
+
+    let hall_of_fame = g.get_hall_of_fame();
+    assert_eq!(hall_of_fame.len(), 5);
+    // the best evaluation ever seen, even if it is no longer in the population
+    assert_eq!(hall_of_fame[0].evals, g.top_memo(1)[0].1);
+    assert!(hall_of_fame.windows(2).all(|w| w[0].evals >= w[1].evals));
+    let members = g.hall_of_fame_as_ref().members();
+    for (i, member) in members.iter().enumerate() {
+        assert!(members[..i].iter().all(|m| m.features != member.features));
+    }
+
+    let json = std::env::temp_dir().join(format!("ys_de_{}_hall_of_fame.json", std::process::id()));
+    let json = json.to_str().unwrap();
+    g.save_to_json(json);
+    let mut loaded = group::Group::<Rastrigin>::load_from_json(json, 0);
+    let features = |g: &group::Group<Rastrigin>| -> Vec<Vec<f64>> {
+        g.hall_of_fame_as_ref()
+            .members()
+            .iter()
+            .map(|m| m.features.clone())
+            .collect()
+    };
+    assert_eq!(features(&loaded), features(&g));
+    std::fs::remove_file(json).unwrap();
+
+    loaded.set_hall_of_fame_capacity(2);
+    assert_eq!(loaded.get_hall_of_fame().len(), 2);
+}
+
+/// Deb's F2: five peaks of decreasing height in [0, 1]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Peaks {
+    genes: Vec<f64>,
+    features: Vec<f64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Peaks {
+    type Feature = f64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes.clone()
+    }
+
+    fn evaluate(&self) -> Vec<f64> {
+        let x = self.features[0];
+        let envelope = (-2.0 * 2f64.ln() * ((x - 0.1) / 0.8).powi(2)).exp();
+        let offset = OFFSET.with(Cell::get);
+        vec![envelope * (5.0 * std::f64::consts::PI * x).sin().powi(6) + offset]
+    }
+}
+
+thread_local! {
+    /// added to every evaluation of Peaks
+    static OFFSET: Cell<f64> = const { Cell::new(0.0) };
+}
+
+/// distinct optima close to a peak
+fn found_peaks(g: &group::Group<Peaks>, space: Space) -> usize {
+    g.get_distinct_optima(0.1, space)
+        .iter()
+        .filter(|(_, i)| {
+            (i.features[0] - 0.1 - 0.2 * ((i.features[0] - 0.1) / 0.2).round()).abs() < 0.01
+        })
+        .count()
+}
+
+#[test]
+fn niching_keeps_several_optima() {
+    let mut plain = group::Group::<Peaks>::from_shape(30, 1, 0);
+    plain.advance_epoch(100, "rand", 1, 0.5, 0.9);
+    assert_eq!(found_peaks(&plain, Space::Genes), 1);
+
+    for niching in [
+        Niching::Crowding,
+        Niching::Speciation { radius: 0.1 },
+        Niching::Sharing {
+            radius: 0.1,
+            alpha: 1.0,
+        },
+    ] {
+        for space in [Space::Genes, Space::Features] {
+            let mut g = group::Group::<Peaks>::from_shape(30, 1, 0);
+            g.options_as_mut().niching = niching;
+            g.options_as_mut().niching_space = space;
+            g.advance_epoch(100, "rand", 1, 0.5, 0.9);
+            assert_eq!(found_peaks(&g, space), 5, "{:?}", niching);
+            // the global optimum comes first
+            let optima = g.get_distinct_optima(0.1, space);
+            assert_eq!(optima[0].1.evals, g.get_best().1.evals);
+        }
+    }
+}
+
+#[test]
+fn sharing_with_negative_evaluations() {
+    OFFSET.with(|offset| offset.set(-1.0));
+    let mut g = group::Group::<Peaks>::from_shape(30, 1, 0);
+    g.options_as_mut().niching = Niching::Sharing {
+        radius: 0.1,
+        alpha: 1.0,
+    };
+    g.advance_epoch(100, "rand", 1, 0.5, 0.9);
+    assert!(g.get_best().1.evals[0] <= 0.0);
+    assert_eq!(found_peaks(&g, Space::Genes), 5);
+}
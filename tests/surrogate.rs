@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use ys_differential_evolution::group;
+use ys_differential_evolution::group::*;
+use ys_differential_evolution::individual;
+use ys_differential_evolution::memo::MemoKeying;
+use ys_differential_evolution::method;
+use ys_differential_evolution::method::ExtMemoizationDE;
+use ys_differential_evolution::surrogate::*;
+
+/// smooth, optimum at genes = 0.3, 0.4, ...
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Bowl {
+    genes: Vec<f64>,
+    features: Vec<f64>,
+    evals: Vec<f64>,
+}
+
+impl individual::Minimum for Bowl {
+    type Feature = f64;
+
+    fn new() -> Self {
+        Self {
+            genes: vec![],
+            features: vec![],
+            evals: vec![],
+        }
+    }
+
+    fn set_genes(&mut self, genes: Vec<f64>) {
+        self.genes = genes;
+    }
+
+    fn get_genes(&self) -> &Vec<f64> {
+        &self.genes
+    }
+
+    fn set_features(&mut self, features: Vec<Self::Feature>) {
+        self.features = features
+    }
+
+    fn get_features(&self) -> &Vec<Self::Feature> {
+        &self.features
+    }
+
+    fn set_evaluations(&mut self, evaluations: Vec<f64>) {
+        self.evals = evaluations;
+    }
+
+    fn get_evaluations(&self) -> &Vec<f64> {
+        &self.evals
+    }
+
+    fn identificate(&self) -> Vec<Self::Feature> {
+        self.genes.clone()
+    }
+
+    fn evaluate(&self) -> Vec<f64> {
+        let sum = self.features.iter().enumerate().fold(0.0, |a, (i, x)| {
+            let d = x - 0.3 - 0.1 * i as f64;
+            a + (i + 1) as f64 * d * d
+        });
+        vec![-sum]
+    }
+}
+
+fn run(surrogate: Option<Surrogate>, seed: u64) -> group::Group<Bowl> {
+    let mut g = group::Group::<Bowl>::from_shape(10, 4, seed);
+    g.options_as_mut().surrogate = surrogate;
+    g.advance_epoch(30, "rand", 1, 0.5, 0.9);
+    g
+}
+
+#[test]
+fn surrogate_prescreening() {
+    let surrogate = Surrogate {
+        candidates: 4,
+        retrain_interval: 3,
+        max_points: 100,
+    };
+    for seed in 0..4 {
+        let plain = run(None, seed);
+        let screened = run(Some(surrogate.clone()), seed);
+        // one true evaluation per target either way
+        let lookups = |g: &group::Group<Bowl>| g.get_memo_stats().hits + g.get_memo_stats().misses;
+        assert_eq!(lookups(&plain), lookups(&screened));
+        assert!(screened.get_best().1.evals > plain.get_best().1.evals);
+    }
+
+    let screened = run(Some(surrogate.clone()), 0);
+    let report = screened.get_surrogate_report();
+    assert_eq!(report.trainings.len(), 10);
+    assert!(report.trainings.iter().all(|t| t.points <= 100));
+    assert!(report.trainings[..9].iter().all(|t| t.tested > 0));
+    // the model gets better as the memo grows
+    assert!(report.trainings[8].mean_absolute_error < report.trainings[0].mean_absolute_error);
+    assert!(report.mean_absolute_error().unwrap() > 0.0);
+
+    let again = run(Some(surrogate), 0);
+    assert_eq!(again.get_best().1.genes, screened.get_best().1.genes);
+}
+
+#[test]
+fn surrogate_is_skipped_with_hashed_memo_keys() {
+    let mut g = group::Group::<Bowl>::from_shape(10, 4, 0);
+    g.set_memo_keying(MemoKeying::Hash128);
+    g.options_as_mut().surrogate = Some(Surrogate {
+        candidates: 4,
+        retrain_interval: 3,
+        max_points: 100,
+    });
+    g.advance_epoch(30, "rand", 1, 0.5, 0.9);
+    // hashed keys cannot be turned back into inputs of the model
+    assert!(g.get_surrogate_report().trainings.is_empty());
+    let stats = g.get_memo_stats();
+    assert_eq!(stats.hits + stats.misses, 10 * 31);
+
+    g.retrain_surrogate(100);
+    assert!(g.get_surrogate_report().trainings.is_empty());
+}
+
+#[test]
+fn surrogate_fits_points_across_the_memo() {
+    let mut g = run(None, 0);
+    let memo_len = g.memo_as_ref().len();
+    assert!(memo_len > 40);
+    g.retrain_surrogate(20);
+    assert_eq!(g.get_surrogate_report().trainings[0].points, 20);
+
+    // fitted to the poor entries too, the worst one is not predicted as good as the best
+    let (worst, best) = {
+        let sorted = g.get_sorted_memo();
+        (sorted[sorted.len() - 1].clone(), sorted[0].clone())
+    };
+    let input = |key: &str| -> Vec<f64> { key.split(',').map(|x| x.parse().unwrap()).collect() };
+    let model = g.surrogate_as_ref().model().unwrap();
+    assert!(model.predict(&input(&worst.0)) < model.predict(&input(&best.0)));
+    assert!((model.predict(&input(&worst.0)) - worst.1[0]).abs() < 1e-3);
+}
+
+#[test]
+#[should_panic(expected = "Options::surrogate needs the memo")]
+fn surrogate_is_refused_without_a_memo() {
+    let mut g = group::Group::<Bowl>::from_shape(10, 4, 0);
+    g.options_as_mut().surrogate = Some(Surrogate {
+        candidates: 4,
+        retrain_interval: 3,
+        max_points: 100,
+    });
+    method::ExtConfiguredDE::advance_epoch(&mut g, 1, "rand", 1, 0.5, 0.9);
+}